@@ -0,0 +1,197 @@
+#ifndef FURIOSA_CLIENT_H
+#define FURIOSA_CLIENT_H
+
+/* Generated by cbindgen from capi/src/lib.rs. Don't edit it manually. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/*
+ * Status of a call. The values are stable across releases.
+ */
+typedef enum FuriosaStatus {
+  FURIOSA_STATUS_OK = 0,
+  FURIOSA_STATUS_IO_ERROR = 1,
+  FURIOSA_STATUS_CONFIG_PARSE = 2,
+  FURIOSA_STATUS_CONFIG_ENV_VAR = 3,
+  FURIOSA_STATUS_NO_API_KEY = 4,
+  FURIOSA_STATUS_API_ERROR = 5,
+  FURIOSA_STATUS_COMPILATION_FAILED = 6,
+  FURIOSA_STATUS_INVALID_RUNTIME_VERSION = 7,
+  FURIOSA_STATUS_UNSUPPORTED = 8,
+  FURIOSA_STATUS_INCOMPATIBLE_SERVER = 9,
+  FURIOSA_STATUS_INVALID_TARGET_IR = 10,
+  FURIOSA_STATUS_INVALID_MODEL = 11,
+  FURIOSA_STATUS_DOWNLOAD = 12,
+  FURIOSA_STATUS_CHECKSUM_MISMATCH = 13,
+  FURIOSA_STATUS_UNSUPPORTED_NPU_SPEC = 14,
+  FURIOSA_STATUS_CASSETTE = 15,
+  FURIOSA_STATUS_RATE_LIMITED = 16,
+  FURIOSA_STATUS_NESTED_RUNTIME = 17,
+  FURIOSA_STATUS_INVALID_LABEL = 18,
+  FURIOSA_STATUS_SERVER_ERROR = 19,
+  FURIOSA_STATUS_TRANSPORT = 20,
+  /*
+   * A null pointer, a string which isn't UTF-8 or an invalid JSON is given
+   */
+  FURIOSA_STATUS_INVALID_ARGUMENT = 100,
+  /*
+   * The compile task hasn't completed yet
+   */
+  FURIOSA_STATUS_PENDING = 101,
+  /*
+   * The client panicked, which is a bug
+   */
+  FURIOSA_STATUS_PANIC = 102,
+} FuriosaStatus;
+
+/*
+ * Opaque handle of a client
+ */
+typedef struct FuriosaClientHandle FuriosaClientHandle;
+
+/*
+ * Opaque handle of a compilation running in the background
+ */
+typedef struct FuriosaTask FuriosaTask;
+
+/*
+ * Bytes allocated by the client, which must be freed by `furiosa_buffer_free`
+ */
+typedef struct FuriosaBuffer {
+  uint8_t *data;
+  uintptr_t len;
+} FuriosaBuffer;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/*
+ * Creates a client configured by the environment variables and `$HOME/.furiosa`.
+ *
+ * # Safety
+ * `runtime_version` must be a null-terminated string and `out` must be a valid pointer.
+ */
+FuriosaStatus furiosa_client_new(const char *runtime_version, FuriosaClientHandle **out);
+
+/*
+ * Creates a client with the given endpoint and API keys.
+ *
+ * # Safety
+ * The strings must be null-terminated and `out` must be a valid pointer.
+ */
+FuriosaStatus furiosa_client_with_credential(const char *runtime_version,
+                                             const char *endpoint,
+                                             const char *access_key_id,
+                                             const char *secret_access_key,
+                                             FuriosaClientHandle **out);
+
+/*
+ * Frees a client. Tasks created by the client keep working until they are freed.
+ *
+ * # Safety
+ * `client` must be null or a pointer returned by `furiosa_client_new` which isn't freed yet.
+ */
+void furiosa_client_free(FuriosaClientHandle *client);
+
+/*
+ * Compiles a model and blocks until the artifact is downloaded into `out`.
+ * `npu_spec` and `compiler_config` are JSON. `compiler_config` and `target_ir` may be null.
+ *
+ * # Safety
+ * `client` must be a valid client, the strings must be null-terminated or null, `model` must
+ * point to `model_len` bytes and `out` must be a valid pointer.
+ */
+FuriosaStatus furiosa_compile(const FuriosaClientHandle *client,
+                              const char *npu_spec,
+                              const char *compiler_config,
+                              const char *target_ir,
+                              const uint8_t *model,
+                              uintptr_t model_len,
+                              FuriosaBuffer *out);
+
+/*
+ * Starts compiling a model in the background. The arguments are the same as
+ * `furiosa_compile`, and the task is written into `out`.
+ *
+ * # Safety
+ * The same as `furiosa_compile`.
+ */
+FuriosaStatus furiosa_compile_submit(const FuriosaClientHandle *client,
+                                     const char *npu_spec,
+                                     const char *compiler_config,
+                                     const char *target_ir,
+                                     const uint8_t *model,
+                                     uintptr_t model_len,
+                                     FuriosaTask **out);
+
+/*
+ * Returns `FuriosaStatus::Pending` without blocking if the task is running. Otherwise, writes
+ * the artifact into `out` and returns `FuriosaStatus::Ok`, or returns the error of the task.
+ *
+ * # Safety
+ * `task` must be a valid task and `out` must be a valid pointer.
+ */
+FuriosaStatus furiosa_task_try_wait(FuriosaTask *task, FuriosaBuffer *out);
+
+/*
+ * Blocks until the task completes, and writes the artifact into `out`.
+ *
+ * # Safety
+ * `task` must be a valid task and `out` must be a valid pointer.
+ */
+FuriosaStatus furiosa_task_wait(FuriosaTask *task, FuriosaBuffer *out);
+
+/*
+ * Frees a task. A running compilation is aborted, and stops polling and downloading.
+ *
+ * # Safety
+ * `task` must be null or a pointer returned by `furiosa_compile_submit` which isn't freed yet.
+ */
+void furiosa_task_free(FuriosaTask *task);
+
+/*
+ * Quantizes an ONNX model with dynamic ranges given as JSON (e.g., `{"input": [0.0, 1.0]}`).
+ * `input_tensors` is a JSON array of tensor names, which may be null to use the graph inputs.
+ *
+ * # Safety
+ * `client` must be a valid client, the strings must be null-terminated or null, `model` must
+ * point to `model_len` bytes and `out` must be a valid pointer.
+ */
+FuriosaStatus furiosa_quantize(const FuriosaClientHandle *client,
+                               const uint8_t *model,
+                               uintptr_t model_len,
+                               const char *dynamic_ranges,
+                               const char *input_tensors,
+                               FuriosaBuffer *out);
+
+/*
+ * Frees a buffer returned by the client
+ *
+ * # Safety
+ * `buffer` must be returned by the client and not be freed yet.
+ */
+void furiosa_buffer_free(FuriosaBuffer buffer);
+
+/*
+ * Returns the status of the last error in the calling thread, or `FuriosaStatus::Ok`
+ */
+FuriosaStatus furiosa_last_error_status(void);
+
+/*
+ * Returns the message of the last error in the calling thread, or null. The message is valid
+ * until the next call of the thread which fails.
+ */
+const char *furiosa_last_error_message(void);
+
+/*
+ * Returns an empty buffer, e.g., to initialize a buffer before a call
+ */
+FuriosaBuffer furiosa_buffer_empty(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif /* FURIOSA_CLIENT_H */
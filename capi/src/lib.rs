@@ -0,0 +1,481 @@
+//! C ABI of the client, built as `libfuriosa_client_capi`
+//!
+//! The client and compile tasks are opaque handles created and freed by the functions here.
+//! Functions return `FuriosaStatus`, and the message of the last error of the calling thread
+//! is returned by `furiosa_last_error_message`. Artifacts are returned as `FuriosaBuffer`,
+//! which the caller must free with `furiosa_buffer_free`.
+//!
+//! `include/furiosa_client.h` is generated by cbindgen with `cbindgen.toml`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+
+use serde_json::Value;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use furiosa_client::blocking;
+use furiosa_client::{
+    ClientError, CompileRequest, CompiledModel, ModelSource, QuantizeRequest, TargetIr,
+};
+
+/// Status of a call. The values are stable across releases.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FuriosaStatus {
+    Ok = 0,
+    IoError = 1,
+    ConfigParse = 2,
+    ConfigEnvVar = 3,
+    NoApiKey = 4,
+    ApiError = 5,
+    CompilationFailed = 6,
+    InvalidRuntimeVersion = 7,
+    Unsupported = 8,
+    IncompatibleServer = 9,
+    InvalidTargetIr = 10,
+    InvalidModel = 11,
+    Download = 12,
+    ChecksumMismatch = 13,
+    UnsupportedNpuSpec = 14,
+    Cassette = 15,
+    RateLimited = 16,
+    NestedRuntime = 17,
+    InvalidLabel = 18,
+    ServerError = 19,
+    Transport = 20,
+    /// A null pointer, a string which isn't UTF-8 or an invalid JSON is given
+    InvalidArgument = 100,
+    /// The compile task hasn't completed yet
+    Pending = 101,
+    /// The client panicked, which is a bug
+    Panic = 102,
+}
+
+impl From<&ClientError> for FuriosaStatus {
+    fn from(e: &ClientError) -> Self {
+        match e {
+            ClientError::Io(_) => FuriosaStatus::IoError,
+            ClientError::ConfigParse(..) => FuriosaStatus::ConfigParse,
+            ClientError::ConfigEnvVar(_) => FuriosaStatus::ConfigEnvVar,
+            ClientError::NoApiKey => FuriosaStatus::NoApiKey,
+            ClientError::ApiError(_) => FuriosaStatus::ApiError,
+            ClientError::ServerError(..) => FuriosaStatus::ServerError,
+            ClientError::Transport(_) => FuriosaStatus::Transport,
+            ClientError::CompilationFailed(_) => FuriosaStatus::CompilationFailed,
+            ClientError::InvalidRuntimeVersion(_) => FuriosaStatus::InvalidRuntimeVersion,
+            ClientError::Unsupported(_) => FuriosaStatus::Unsupported,
+            ClientError::IncompatibleServer(_) => FuriosaStatus::IncompatibleServer,
+            ClientError::InvalidTargetIr(_) => FuriosaStatus::InvalidTargetIr,
+            ClientError::InvalidLabel(_) => FuriosaStatus::InvalidLabel,
+            ClientError::InvalidModel(_) => FuriosaStatus::InvalidModel,
+            ClientError::Download(_) => FuriosaStatus::Download,
+            ClientError::ChecksumMismatch(..) => FuriosaStatus::ChecksumMismatch,
+            ClientError::UnsupportedNpuSpec(_) => FuriosaStatus::UnsupportedNpuSpec,
+            ClientError::Cassette(_) => FuriosaStatus::Cassette,
+            ClientError::RateLimited(..) => FuriosaStatus::RateLimited,
+            ClientError::NestedRuntime => FuriosaStatus::NestedRuntime,
+        }
+    }
+}
+
+/// Bytes allocated by the client, which must be freed by `furiosa_buffer_free`
+#[repr(C)]
+pub struct FuriosaBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl FuriosaBuffer {
+    fn empty() -> FuriosaBuffer {
+        FuriosaBuffer { data: ptr::null_mut(), len: 0 }
+    }
+
+    fn from_boxed(bytes: Box<[u8]>) -> FuriosaBuffer {
+        let len = bytes.len();
+        FuriosaBuffer { data: Box::into_raw(bytes) as *mut u8, len }
+    }
+}
+
+/// Opaque handle of a client
+pub struct FuriosaClientHandle {
+    client: Arc<blocking::FuriosaClient>,
+}
+
+/// Opaque handle of a compilation running in the background
+pub struct FuriosaTask {
+    client: Arc<blocking::FuriosaClient>,
+    compilation: JoinHandle<()>,
+    receiver: Option<oneshot::Receiver<Result<Box<[u8]>, ClientError>>>,
+    result: Option<Result<Box<[u8]>, ClientError>>,
+}
+
+thread_local! {
+    #[allow(clippy::missing_const_for_thread_local)]
+    static LAST_ERROR: RefCell<Option<(FuriosaStatus, CString)>> = RefCell::new(None);
+}
+
+fn set_last_error(status: FuriosaStatus, message: &str) -> FuriosaStatus {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some((status, message)));
+    status
+}
+
+fn set_client_error(e: &ClientError) -> FuriosaStatus {
+    set_last_error(e.into(), &e.to_string())
+}
+
+/// Runs `f` and turns panics into `FuriosaStatus::Panic`
+fn guard<F: FnOnce() -> FuriosaStatus>(f: F) -> FuriosaStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(status) => status,
+        Err(_) => set_last_error(FuriosaStatus::Panic, "the client panicked"),
+    }
+}
+
+/// Reads a UTF-8 string, which is `None` for a null pointer
+unsafe fn read_str<'a>(name: &str, s: *const c_char) -> Result<Option<&'a str>, FuriosaStatus> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(s).to_str().map(Some).map_err(|_| {
+        set_last_error(FuriosaStatus::InvalidArgument, &format!("{} is not UTF-8", name))
+    })
+}
+
+unsafe fn require_str<'a>(name: &str, s: *const c_char) -> Result<&'a str, FuriosaStatus> {
+    read_str(name, s)?
+        .ok_or_else(|| set_last_error(FuriosaStatus::InvalidArgument, &format!("{} is null", name)))
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(name: &str, json: &str) -> Result<T, FuriosaStatus> {
+    serde_json::from_str(json).map_err(|e| {
+        set_last_error(FuriosaStatus::InvalidArgument, &format!("invalid {}: {}", name, e))
+    })
+}
+
+unsafe fn read_bytes(name: &str, data: *const u8, len: usize) -> Result<Vec<u8>, FuriosaStatus> {
+    if data.is_null() {
+        return Err(set_last_error(FuriosaStatus::InvalidArgument, &format!("{} is null", name)));
+    }
+    Ok(std::slice::from_raw_parts(data, len).to_vec())
+}
+
+macro_rules! try_status {
+    ($e:expr) => {
+        match $e {
+            Ok(value) => value,
+            Err(status) => return status,
+        }
+    };
+}
+
+unsafe fn compile_request(
+    npu_spec: *const c_char,
+    compiler_config: *const c_char,
+    target_ir: *const c_char,
+    model: *const u8,
+    model_len: usize,
+) -> Result<CompileRequest, FuriosaStatus> {
+    let npu_spec: Value = parse_json("npu_spec", require_str("npu_spec", npu_spec)?)?;
+    let model = read_bytes("model", model, model_len)?;
+    let mut request = CompileRequest::new(npu_spec, model);
+    if let Some(config) = read_str("compiler_config", compiler_config)? {
+        request = request.compile_config(parse_json("compiler_config", config)?);
+    }
+    if let Some(target_ir) = read_str("target_ir", target_ir)? {
+        let target_ir = target_ir.parse::<TargetIr>().map_err(|e| set_client_error(&e))?;
+        request = request.target_ir(target_ir);
+    }
+    Ok(request)
+}
+
+fn write_result(result: Result<Box<[u8]>, ClientError>, out: *mut FuriosaBuffer) -> FuriosaStatus {
+    match result {
+        Ok(bytes) => {
+            unsafe { *out = FuriosaBuffer::from_boxed(bytes) };
+            FuriosaStatus::Ok
+        }
+        Err(e) => set_client_error(&e),
+    }
+}
+
+fn new_client(
+    client: Result<blocking::FuriosaClient, ClientError>,
+    out: *mut *mut FuriosaClientHandle,
+) -> FuriosaStatus {
+    match client {
+        Ok(client) => {
+            let handle = FuriosaClientHandle { client: Arc::new(client) };
+            unsafe { *out = Box::into_raw(Box::new(handle)) };
+            FuriosaStatus::Ok
+        }
+        Err(e) => set_client_error(&e),
+    }
+}
+
+/// Creates a client configured by the environment variables and `$HOME/.furiosa`.
+///
+/// # Safety
+/// `runtime_version` must be a null-terminated string and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_client_new(
+    runtime_version: *const c_char,
+    out: *mut *mut FuriosaClientHandle,
+) -> FuriosaStatus {
+    guard(|| {
+        if out.is_null() {
+            return set_last_error(FuriosaStatus::InvalidArgument, "out is null");
+        }
+        let runtime_version = try_status!(require_str("runtime_version", runtime_version));
+        new_client(blocking::FuriosaClient::new(runtime_version), out)
+    })
+}
+
+/// Creates a client with the given endpoint and API keys.
+///
+/// # Safety
+/// The strings must be null-terminated and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_client_with_credential(
+    runtime_version: *const c_char,
+    endpoint: *const c_char,
+    access_key_id: *const c_char,
+    secret_access_key: *const c_char,
+    out: *mut *mut FuriosaClientHandle,
+) -> FuriosaStatus {
+    guard(|| {
+        if out.is_null() {
+            return set_last_error(FuriosaStatus::InvalidArgument, "out is null");
+        }
+        let client = blocking::FuriosaClient::with_credential(
+            try_status!(require_str("runtime_version", runtime_version)),
+            try_status!(require_str("endpoint", endpoint)),
+            try_status!(require_str("access_key_id", access_key_id)),
+            try_status!(require_str("secret_access_key", secret_access_key)),
+        );
+        new_client(client, out)
+    })
+}
+
+/// Frees a client. Tasks created by the client keep working until they are freed.
+///
+/// # Safety
+/// `client` must be null or a pointer returned by `furiosa_client_new` which isn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_client_free(client: *mut FuriosaClientHandle) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Compiles a model and blocks until the artifact is downloaded into `out`.
+/// `npu_spec` and `compiler_config` are JSON. `compiler_config` and `target_ir` may be null.
+///
+/// # Safety
+/// `client` must be a valid client, the strings must be null-terminated or null, `model` must
+/// point to `model_len` bytes and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_compile(
+    client: *const FuriosaClientHandle,
+    npu_spec: *const c_char,
+    compiler_config: *const c_char,
+    target_ir: *const c_char,
+    model: *const u8,
+    model_len: usize,
+    out: *mut FuriosaBuffer,
+) -> FuriosaStatus {
+    guard(|| {
+        if client.is_null() || out.is_null() {
+            return set_last_error(FuriosaStatus::InvalidArgument, "client or out is null");
+        }
+        let request =
+            try_status!(compile_request(npu_spec, compiler_config, target_ir, model, model_len));
+        write_result((*client).client.compile(request).map(CompiledModel::into_bytes), out)
+    })
+}
+
+/// Starts compiling a model in the background. The arguments are the same as
+/// `furiosa_compile`, and the task is written into `out`.
+///
+/// # Safety
+/// The same as `furiosa_compile`.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_compile_submit(
+    client: *const FuriosaClientHandle,
+    npu_spec: *const c_char,
+    compiler_config: *const c_char,
+    target_ir: *const c_char,
+    model: *const u8,
+    model_len: usize,
+    out: *mut *mut FuriosaTask,
+) -> FuriosaStatus {
+    guard(|| {
+        if client.is_null() || out.is_null() {
+            return set_last_error(FuriosaStatus::InvalidArgument, "client or out is null");
+        }
+        let request =
+            try_status!(compile_request(npu_spec, compiler_config, target_ir, model, model_len));
+        let client = (*client).client.clone();
+        let (sender, receiver) = oneshot::channel();
+        let inner = client.clone();
+        let compilation = client.handle().spawn(async move {
+            let _ =
+                sender.send(inner.as_async().compile(request).await.map(CompiledModel::into_bytes));
+        });
+        let task = FuriosaTask { client, compilation, receiver: Some(receiver), result: None };
+        *out = Box::into_raw(Box::new(task));
+        FuriosaStatus::Ok
+    })
+}
+
+/// Returns `FuriosaStatus::Pending` without blocking if the task is running. Otherwise, writes
+/// the artifact into `out` and returns `FuriosaStatus::Ok`, or returns the error of the task.
+///
+/// # Safety
+/// `task` must be a valid task and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_task_try_wait(
+    task: *mut FuriosaTask,
+    out: *mut FuriosaBuffer,
+) -> FuriosaStatus {
+    guard(|| {
+        if task.is_null() || out.is_null() {
+            return set_last_error(FuriosaStatus::InvalidArgument, "task or out is null");
+        }
+        let task = &mut *task;
+        if let Some(receiver) = &mut task.receiver {
+            match receiver.try_recv() {
+                Ok(result) => task.result = Some(result),
+                Err(oneshot::error::TryRecvError::Empty) => return FuriosaStatus::Pending,
+                Err(oneshot::error::TryRecvError::Closed) => {
+                    return set_last_error(FuriosaStatus::Panic, "the task is dropped");
+                }
+            }
+            task.receiver = None;
+        }
+        take_result(task, out)
+    })
+}
+
+/// Blocks until the task completes, and writes the artifact into `out`.
+///
+/// # Safety
+/// `task` must be a valid task and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_task_wait(
+    task: *mut FuriosaTask,
+    out: *mut FuriosaBuffer,
+) -> FuriosaStatus {
+    guard(|| {
+        if task.is_null() || out.is_null() {
+            return set_last_error(FuriosaStatus::InvalidArgument, "task or out is null");
+        }
+        let task = &mut *task;
+        if let Some(receiver) = task.receiver.take() {
+            if tokio::runtime::Handle::try_current().is_ok() {
+                return set_client_error(&ClientError::NestedRuntime);
+            }
+            match task.client.handle().block_on(receiver) {
+                Ok(result) => task.result = Some(result),
+                Err(_) => return set_last_error(FuriosaStatus::Panic, "the task is dropped"),
+            }
+        }
+        take_result(task, out)
+    })
+}
+
+fn take_result(task: &mut FuriosaTask, out: *mut FuriosaBuffer) -> FuriosaStatus {
+    match task.result.take() {
+        Some(result) => write_result(result, out),
+        None => set_last_error(FuriosaStatus::InvalidArgument, "the result is already taken"),
+    }
+}
+
+/// Frees a task. A running compilation is aborted, and stops polling and downloading.
+///
+/// # Safety
+/// `task` must be null or a pointer returned by `furiosa_compile_submit` which isn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_task_free(task: *mut FuriosaTask) {
+    if !task.is_null() {
+        let task = Box::from_raw(task);
+        task.compilation.abort();
+    }
+}
+
+/// Quantizes an ONNX model with dynamic ranges given as JSON (e.g., `{"input": [0.0, 1.0]}`).
+/// `input_tensors` is a JSON array of tensor names, which may be null to use the graph inputs.
+///
+/// # Safety
+/// `client` must be a valid client, the strings must be null-terminated or null, `model` must
+/// point to `model_len` bytes and `out` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_quantize(
+    client: *const FuriosaClientHandle,
+    model: *const u8,
+    model_len: usize,
+    dynamic_ranges: *const c_char,
+    input_tensors: *const c_char,
+    out: *mut FuriosaBuffer,
+) -> FuriosaStatus {
+    guard(|| {
+        if client.is_null() || out.is_null() {
+            return set_last_error(FuriosaStatus::InvalidArgument, "client or out is null");
+        }
+        let model = try_status!(read_bytes("model", model, model_len));
+        let dynamic_ranges: HashMap<String, (f32, f32)> = try_status!(parse_json(
+            "dynamic_ranges",
+            try_status!(require_str("dynamic_ranges", dynamic_ranges))
+        ));
+        let request = match try_status!(read_str("input_tensors", input_tensors)) {
+            Some(input_tensors) => QuantizeRequest {
+                filename: String::from("noname"),
+                source: ModelSource::Bytes(model),
+                input_tensors: try_status!(parse_json("input_tensors", input_tensors)),
+                dynamic_ranges,
+                labels: Default::default(),
+            },
+            None => match QuantizeRequest::from_model(model, dynamic_ranges) {
+                Ok(request) => request,
+                Err(e) => return set_client_error(&e),
+            },
+        };
+        write_result((*client).client.quantize(request), out)
+    })
+}
+
+/// Frees a buffer returned by the client
+///
+/// # Safety
+/// `buffer` must be returned by the client and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn furiosa_buffer_free(buffer: FuriosaBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(buffer.data, buffer.len)));
+    }
+}
+
+/// Returns the status of the last error in the calling thread, or `FuriosaStatus::Ok`
+#[no_mangle]
+pub extern "C" fn furiosa_last_error_status() -> FuriosaStatus {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(FuriosaStatus::Ok, |(status, _)| *status))
+}
+
+/// Returns the message of the last error in the calling thread, or null. The message is valid
+/// until the next call of the thread which fails.
+#[no_mangle]
+pub extern "C" fn furiosa_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |(_, message)| message.as_ptr()))
+}
+
+/// Returns an empty buffer, e.g., to initialize a buffer before a call
+#[no_mangle]
+pub extern "C" fn furiosa_buffer_empty() -> FuriosaBuffer {
+    FuriosaBuffer::empty()
+}
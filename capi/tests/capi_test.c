@@ -0,0 +1,86 @@
+/*
+ * Runs the C API against the mock server of tests/capi_test.rs:
+ *   capi_test <endpoint>
+ */
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "furiosa_client.h"
+
+#define CHECK(cond)                                                        \
+  do {                                                                     \
+    if (!(cond)) {                                                         \
+      const char *message = furiosa_last_error_message();                  \
+      fprintf(stderr, "%s:%d: %s failed (%s)\n", __FILE__, __LINE__, #cond, \
+              message ? message : "no error");                             \
+      return 1;                                                            \
+    }                                                                      \
+  } while (0)
+
+static const char *NPU_SPEC = "{\"npu\": \"64dpes\"}";
+static const uint8_t MODEL[] = "model";
+
+static int buffer_equals(FuriosaBuffer buffer, const char *expected) {
+  return buffer.len == strlen(expected) && memcmp(buffer.data, expected, buffer.len) == 0;
+}
+
+int main(int argc, char **argv) {
+  FuriosaClientHandle *client = NULL;
+  FuriosaTask *task = NULL;
+  FuriosaBuffer buffer = furiosa_buffer_empty();
+  FuriosaStatus status;
+
+  if (argc != 2) {
+    fprintf(stderr, "usage: %s <endpoint>\n", argv[0]);
+    return 2;
+  }
+
+  CHECK(furiosa_client_with_credential("0.4.0", argv[1], "mock-key", "mock-secret", &client) ==
+        FURIOSA_STATUS_OK);
+
+  /* compiles synchronously */
+  CHECK(furiosa_compile(client, NPU_SPEC, NULL, "enf", MODEL, sizeof(MODEL) - 1, &buffer) ==
+        FURIOSA_STATUS_OK);
+  CHECK(buffer_equals(buffer, "ENF"));
+  furiosa_buffer_free(buffer);
+
+  /* compiles in the background */
+  CHECK(furiosa_compile_submit(client, NPU_SPEC, NULL, NULL, MODEL, sizeof(MODEL) - 1, &task) ==
+        FURIOSA_STATUS_OK);
+  status = furiosa_task_try_wait(task, &buffer);
+  CHECK(status == FURIOSA_STATUS_PENDING || status == FURIOSA_STATUS_OK);
+  if (status == FURIOSA_STATUS_PENDING) {
+    CHECK(furiosa_task_wait(task, &buffer) == FURIOSA_STATUS_OK);
+  }
+  CHECK(buffer_equals(buffer, "ENF2"));
+  furiosa_buffer_free(buffer);
+  CHECK(furiosa_task_wait(task, &buffer) == FURIOSA_STATUS_INVALID_ARGUMENT);
+  furiosa_task_free(task);
+
+  /* fails with stable status codes and messages */
+  CHECK(furiosa_compile(client, NPU_SPEC, NULL, "xyz", MODEL, sizeof(MODEL) - 1, &buffer) ==
+        FURIOSA_STATUS_INVALID_TARGET_IR);
+  CHECK(furiosa_last_error_status() == FURIOSA_STATUS_INVALID_TARGET_IR);
+  CHECK(strstr(furiosa_last_error_message(), "xyz") != NULL);
+  CHECK(furiosa_compile(client, "{", NULL, NULL, MODEL, sizeof(MODEL) - 1, &buffer) ==
+        FURIOSA_STATUS_INVALID_ARGUMENT);
+  CHECK(furiosa_compile(client, NPU_SPEC, NULL, NULL, MODEL, sizeof(MODEL) - 1, &buffer) ==
+        FURIOSA_STATUS_COMPILATION_FAILED);
+
+  /* quantizes */
+  CHECK(furiosa_quantize(client, MODEL, sizeof(MODEL) - 1, "{\"input\": [0.0, 1.0]}",
+                         "[\"input\"]", &buffer) == FURIOSA_STATUS_OK);
+  CHECK(buffer_equals(buffer, "QUANTIZED"));
+  furiosa_buffer_free(buffer);
+
+  /* aborts the compilation of a freed task */
+  CHECK(furiosa_compile_submit(client, NPU_SPEC, NULL, NULL, MODEL, sizeof(MODEL) - 1, &task) ==
+        FURIOSA_STATUS_OK);
+  usleep(300000);
+  furiosa_task_free(task);
+  usleep(1500000);
+
+  furiosa_client_free(client);
+  return 0;
+}
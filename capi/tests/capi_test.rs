@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use furiosa_client::testing::{CompileScript, MockResponse, MockServer};
+use furiosa_client::CompileTaskPhase;
+
+/// Builds tests/capi_test.c against the cdylib of the crate and runs it with the mock server
+#[test]
+fn test_capi() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // The cdylib is built into the directory of the test executable
+    let deps_dir = std::env::current_exe().unwrap().parent().unwrap().to_path_buf();
+    let executable = deps_dir.join("capi_test_c");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| String::from("cc")))
+        .arg(manifest_dir.join("tests/capi_test.c"))
+        .arg("-I")
+        .arg(manifest_dir.join("include"))
+        .arg("-L")
+        .arg(&deps_dir)
+        .arg("-lfuriosa_client_capi")
+        .arg("-o")
+        .arg(&executable)
+        .status()
+        .expect("fail to run the C compiler");
+    assert!(status.success(), "fail to compile tests/capi_test.c");
+
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+    server.script_compile(CompileScript::succeed(b"ENF2".to_vec()));
+    server.script_compile(CompileScript::fail("unsupported operator"));
+    server.respond_dss("quantize", MockResponse::bytes(b"QUANTIZED".to_vec()));
+    let pending = vec![CompileTaskPhase::Pending; 1000];
+    server.script_compile(CompileScript::succeed(b"ENF3".to_vec()).phases(pending));
+
+    let output = Command::new(&executable)
+        .arg(server.endpoint())
+        .env("LD_LIBRARY_PATH", &deps_dir)
+        .env("DYLD_LIBRARY_PATH", &deps_dir)
+        .output()
+        .expect("fail to run the C test");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // The task freed while pending stops polling
+    let freed = server.tasks().pop().unwrap();
+    let polls = server.requests().iter().filter(|r| r.path.ends_with(&freed.task_id)).count();
+    assert!(polls <= 1, "{} polls after the task was freed", polls);
+}
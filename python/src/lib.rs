@@ -0,0 +1,643 @@
+//! Python bindings
+//!
+//! The `furiosa_client` module exposes `FuriosaClient`, the requests and `TargetIr`. Methods
+//! block with the GIL released, and `*_async` methods return awaitables running on the tokio
+//! runtime of pyo3-async-runtimes. Compilations return a `CompiledModel` with the provenance of
+//! the artifact, the DSS operations return `bytes`, and `ClientError` is raised as a subclass of
+//! `furiosa_client.ClientError` (`OSError` for IO errors).
+//!
+//! Build the extension with maturin (see `pyproject.toml` in the root of the workspace).
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use pyo3::exceptions::PyOSError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use serde_json::Value;
+
+use furiosa_client::{
+    CalibrateRequest, ClientError, CompileRequest, CompiledModel, Labels, ModelSource,
+    OptimizeRequest, QuantizeRequest, TargetIr, VersionInfo,
+};
+
+/// Exception classes raised for `ClientError`
+mod exceptions {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyException;
+
+    create_exception!(furiosa_client, ClientError, PyException, "Base class of client errors");
+    create_exception!(furiosa_client, ApiError, ClientError);
+    create_exception!(furiosa_client, CompilationFailed, ClientError);
+    create_exception!(furiosa_client, NoApiKey, ClientError);
+    create_exception!(furiosa_client, ConfigError, ClientError);
+    create_exception!(furiosa_client, InvalidRuntimeVersion, ClientError);
+    create_exception!(furiosa_client, Unsupported, ClientError);
+    create_exception!(furiosa_client, IncompatibleServer, ClientError);
+    create_exception!(furiosa_client, InvalidTargetIr, ClientError);
+    create_exception!(furiosa_client, InvalidModel, ClientError);
+    create_exception!(furiosa_client, InvalidLabel, ClientError);
+    create_exception!(furiosa_client, DownloadError, ClientError);
+    create_exception!(furiosa_client, UnsupportedNpuSpec, ClientError);
+    create_exception!(furiosa_client, RateLimited, ClientError);
+}
+
+/// Converts `ClientError` into the exception of its variant
+fn to_py_err(e: ClientError) -> PyErr {
+    let msg = e.to_string();
+    match e {
+        ClientError::Io(_) => PyOSError::new_err(msg),
+        ClientError::ConfigParse(..) | ClientError::ConfigEnvVar(_) => {
+            exceptions::ConfigError::new_err(msg)
+        }
+        ClientError::NoApiKey => exceptions::NoApiKey::new_err(msg),
+        ClientError::ApiError(_) | ClientError::ServerError(..) | ClientError::Transport(_) => {
+            exceptions::ApiError::new_err(msg)
+        }
+        ClientError::CompilationFailed(_) => exceptions::CompilationFailed::new_err(msg),
+        ClientError::InvalidRuntimeVersion(_) => exceptions::InvalidRuntimeVersion::new_err(msg),
+        ClientError::Unsupported(_) => exceptions::Unsupported::new_err(msg),
+        ClientError::IncompatibleServer(_) => exceptions::IncompatibleServer::new_err(msg),
+        ClientError::InvalidTargetIr(_) => exceptions::InvalidTargetIr::new_err(msg),
+        ClientError::InvalidModel(_) => exceptions::InvalidModel::new_err(msg),
+        ClientError::InvalidLabel(_) => exceptions::InvalidLabel::new_err(msg),
+        ClientError::Download(_) | ClientError::ChecksumMismatch(..) => {
+            exceptions::DownloadError::new_err(msg)
+        }
+        ClientError::UnsupportedNpuSpec(_) => exceptions::UnsupportedNpuSpec::new_err(msg),
+        ClientError::RateLimited(..) => exceptions::RateLimited::new_err(msg),
+        ClientError::Cassette(_) | ClientError::NestedRuntime => {
+            exceptions::ClientError::new_err(msg)
+        }
+    }
+}
+
+#[pyclass(name = "TargetIr", eq, eq_int)]
+#[derive(Copy, Clone, PartialEq)]
+enum PyTargetIr {
+    Dfg,
+    Ldfg,
+    Cdfg,
+    Gir,
+    Lir,
+    Enf,
+}
+
+#[pymethods]
+impl PyTargetIr {
+    /// Parses 'dfg', 'ldfg', 'cdfg', 'gir', 'lir' or 'enf'
+    #[staticmethod]
+    fn parse(s: &str) -> PyResult<PyTargetIr> {
+        Ok(s.parse::<TargetIr>().map_err(to_py_err)?.into())
+    }
+
+    fn __str__(&self) -> String {
+        TargetIr::from(*self).as_str().to_string()
+    }
+}
+
+impl From<PyTargetIr> for TargetIr {
+    fn from(target_ir: PyTargetIr) -> TargetIr {
+        match target_ir {
+            PyTargetIr::Dfg => TargetIr::Dfg,
+            PyTargetIr::Ldfg => TargetIr::Ldfg,
+            PyTargetIr::Cdfg => TargetIr::Cdfg,
+            PyTargetIr::Gir => TargetIr::Gir,
+            PyTargetIr::Lir => TargetIr::Lir,
+            PyTargetIr::Enf => TargetIr::Enf,
+        }
+    }
+}
+
+impl From<TargetIr> for PyTargetIr {
+    fn from(target_ir: TargetIr) -> PyTargetIr {
+        match target_ir {
+            TargetIr::Dfg => PyTargetIr::Dfg,
+            TargetIr::Ldfg => PyTargetIr::Ldfg,
+            TargetIr::Cdfg => PyTargetIr::Cdfg,
+            TargetIr::Gir => PyTargetIr::Gir,
+            TargetIr::Lir => PyTargetIr::Lir,
+            TargetIr::Enf => PyTargetIr::Enf,
+        }
+    }
+}
+
+/// A model given as `bytes`, or a path which is streamed when the request is sent
+#[derive(FromPyObject)]
+enum Model {
+    Bytes(Vec<u8>),
+    Path(PathBuf),
+}
+
+impl Model {
+    fn file_name(&self) -> String {
+        match self {
+            Model::Bytes(_) => String::from("noname"),
+            Model::Path(path) => path
+                .file_name()
+                .map_or_else(|| String::from("noname"), |name| name.to_string_lossy().to_string()),
+        }
+    }
+
+    fn into_source(self) -> ModelSource {
+        match self {
+            Model::Bytes(bytes) => ModelSource::Bytes(bytes),
+            Model::Path(path) => ModelSource::from_path(path),
+        }
+    }
+}
+
+/// Converts a `dict` or a YAML/JSON string into a JSON value
+fn to_json(value: &Bound<'_, PyAny>) -> PyResult<Value> {
+    let text = match value.extract::<String>() {
+        Ok(text) => text,
+        Err(_) => value.py().import("json")?.call_method1("dumps", (value,))?.extract()?,
+    };
+    serde_yaml::from_str(&text)
+        .map_err(|e| exceptions::ClientError::new_err(format!("invalid JSON: {}", e)))
+}
+
+/// `source` of a request can be sent again since it's bytes or a path
+fn clone_source(source: &ModelSource) -> ModelSource {
+    source.try_clone().expect("the source of a Python request is bytes or a path")
+}
+
+#[pyclass(name = "CompileRequest")]
+struct PyCompileRequest {
+    inner: CompileRequest,
+}
+
+#[pymethods]
+impl PyCompileRequest {
+    #[new]
+    #[pyo3(signature = (npu_spec, model, target_ir=PyTargetIr::Enf, compiler_config=None, filename=None, labels=None))]
+    fn new(
+        npu_spec: &Bound<'_, PyAny>,
+        model: Model,
+        target_ir: PyTargetIr,
+        compiler_config: Option<&Bound<'_, PyAny>>,
+        filename: Option<String>,
+        labels: Option<Labels>,
+    ) -> PyResult<PyCompileRequest> {
+        let mut inner = match model {
+            Model::Path(path) => CompileRequest::from_path(to_json(npu_spec)?, path),
+            bytes => CompileRequest::new(to_json(npu_spec)?, bytes.into_source()),
+        };
+        inner = inner.target_ir(target_ir.into());
+        if let Some(config) = compiler_config {
+            inner = inner.compile_config(to_json(config)?);
+        }
+        if let Some(filename) = filename {
+            inner = inner.filename(&filename);
+        }
+        inner.labels = labels.unwrap_or_default();
+        Ok(PyCompileRequest { inner })
+    }
+
+    #[getter]
+    fn target_ir(&self) -> PyTargetIr {
+        self.inner.target_ir.into()
+    }
+
+    #[getter]
+    fn filename(&self) -> &str {
+        &self.inner.filename
+    }
+
+    #[getter]
+    fn labels(&self) -> Labels {
+        self.inner.labels.clone()
+    }
+}
+
+impl PyCompileRequest {
+    fn to_request(&self) -> CompileRequest {
+        self.inner.try_clone().expect("the source of a Python request is bytes or a path")
+    }
+}
+
+#[pyclass(name = "OptimizeRequest")]
+struct PyOptimizeRequest {
+    inner: OptimizeRequest,
+}
+
+#[pymethods]
+impl PyOptimizeRequest {
+    #[new]
+    #[pyo3(signature = (model, filename=None, labels=None))]
+    fn new(model: Model, filename: Option<String>, labels: Option<Labels>) -> PyOptimizeRequest {
+        let mut inner = match model {
+            Model::Path(path) => OptimizeRequest::from_path(path),
+            bytes => OptimizeRequest::new(bytes.into_source()),
+        };
+        if let Some(filename) = filename {
+            inner = inner.filename(&filename);
+        }
+        inner.labels = labels.unwrap_or_default();
+        PyOptimizeRequest { inner }
+    }
+}
+
+impl PyOptimizeRequest {
+    fn to_request(&self) -> OptimizeRequest {
+        OptimizeRequest {
+            filename: self.inner.filename.clone(),
+            source: clone_source(&self.inner.source),
+            labels: self.inner.labels.clone(),
+        }
+    }
+}
+
+#[pyclass(name = "CalibrateRequest")]
+struct PyCalibrateRequest {
+    inner: CalibrateRequest,
+}
+
+#[pymethods]
+impl PyCalibrateRequest {
+    /// `input_tensors` are the graph inputs of the ONNX model if not given
+    #[new]
+    #[pyo3(signature = (model, input_tensors=None, filename=None, labels=None))]
+    fn new(
+        model: Model,
+        input_tensors: Option<Vec<String>>,
+        filename: Option<String>,
+        labels: Option<Labels>,
+    ) -> PyResult<PyCalibrateRequest> {
+        let mut inner = match (model, input_tensors) {
+            (model, Some(input_tensors)) => CalibrateRequest {
+                filename: model.file_name(),
+                source: model.into_source(),
+                input_tensors,
+                labels: Labels::new(),
+            },
+            (Model::Path(path), None) => CalibrateRequest::from_path(path).map_err(to_py_err)?,
+            (bytes, None) => {
+                CalibrateRequest::from_model(bytes.into_source()).map_err(to_py_err)?
+            }
+        };
+        if let Some(filename) = filename {
+            inner = inner.filename(&filename);
+        }
+        inner.labels = labels.unwrap_or_default();
+        Ok(PyCalibrateRequest { inner })
+    }
+
+    #[getter]
+    fn input_tensors(&self) -> Vec<String> {
+        self.inner.input_tensors.clone()
+    }
+}
+
+impl PyCalibrateRequest {
+    fn to_request(&self) -> CalibrateRequest {
+        CalibrateRequest {
+            filename: self.inner.filename.clone(),
+            source: clone_source(&self.inner.source),
+            input_tensors: self.inner.input_tensors.clone(),
+            labels: self.inner.labels.clone(),
+        }
+    }
+}
+
+#[pyclass(name = "QuantizeRequest")]
+struct PyQuantizeRequest {
+    inner: QuantizeRequest,
+}
+
+#[pymethods]
+impl PyQuantizeRequest {
+    /// `dynamic_ranges` maps tensor names to `(min, max)`. `input_tensors` are the graph inputs
+    /// of the ONNX model if not given.
+    #[new]
+    #[pyo3(signature = (model, dynamic_ranges, input_tensors=None, filename=None, labels=None))]
+    fn new(
+        model: Model,
+        dynamic_ranges: HashMap<String, (f32, f32)>,
+        input_tensors: Option<Vec<String>>,
+        filename: Option<String>,
+        labels: Option<Labels>,
+    ) -> PyResult<PyQuantizeRequest> {
+        let mut inner = match (model, input_tensors) {
+            (model, Some(input_tensors)) => QuantizeRequest {
+                filename: model.file_name(),
+                source: model.into_source(),
+                input_tensors,
+                dynamic_ranges,
+                labels: Labels::new(),
+            },
+            (Model::Path(path), None) => {
+                QuantizeRequest::from_path(path, dynamic_ranges).map_err(to_py_err)?
+            }
+            (bytes, None) => QuantizeRequest::from_model(bytes.into_source(), dynamic_ranges)
+                .map_err(to_py_err)?,
+        };
+        if let Some(filename) = filename {
+            inner = inner.filename(&filename);
+        }
+        inner.labels = labels.unwrap_or_default();
+        Ok(PyQuantizeRequest { inner })
+    }
+
+    #[getter]
+    fn input_tensors(&self) -> Vec<String> {
+        self.inner.input_tensors.clone()
+    }
+}
+
+impl PyQuantizeRequest {
+    fn to_request(&self) -> QuantizeRequest {
+        QuantizeRequest {
+            filename: self.inner.filename.clone(),
+            source: clone_source(&self.inner.source),
+            input_tensors: self.inner.input_tensors.clone(),
+            dynamic_ranges: self.inner.dynamic_ranges.clone(),
+            labels: self.inner.labels.clone(),
+        }
+    }
+}
+
+/// An artifact with its provenance, which `bytes()` converts to the artifact
+#[pyclass(name = "CompiledModel")]
+struct PyCompiledModel {
+    inner: CompiledModel,
+}
+
+#[pymethods]
+impl PyCompiledModel {
+    /// Reads the artifacts written by `save` after verifying their checksums
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<PyCompiledModel> {
+        Ok(PyCompiledModel { inner: CompiledModel::load(path).map_err(to_py_err)? })
+    }
+
+    /// Writes the artifacts and the manifest (`<path>.manifest.json`)
+    fn save(&self, path: PathBuf) -> PyResult<()> {
+        self.inner.save(path).map_err(to_py_err)
+    }
+
+    #[getter]
+    fn bytes<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.inner.bytes)
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        self.bytes(py)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.bytes.len()
+    }
+
+    #[getter]
+    fn target_ir(&self) -> PyTargetIr {
+        self.inner.target_ir.into()
+    }
+
+    /// Returns a dict of the artifacts of all requested IRs keyed by their names, e.g., 'enf'
+    #[getter]
+    fn artifacts<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item(self.inner.target_ir.as_str(), self.bytes(py))?;
+        for (target_ir, bytes) in &self.inner.extra_artifacts {
+            dict.set_item(target_ir.as_str(), PyBytes::new(py, bytes))?;
+        }
+        Ok(dict)
+    }
+
+    #[getter]
+    fn filename(&self) -> &str {
+        &self.inner.filename
+    }
+
+    #[getter]
+    fn labels(&self) -> Labels {
+        self.inner.labels.clone()
+    }
+
+    #[getter]
+    fn runtime_version(&self) -> &str {
+        &self.inner.runtime_version
+    }
+
+    /// `None` if the artifact comes from the compile cache
+    #[getter]
+    fn task_id(&self) -> Option<&str> {
+        self.inner.task_id.as_deref()
+    }
+
+    /// Returns a dict of 'version', 'revision' and 'build_time', or `None` if it couldn't be
+    /// fetched
+    #[getter]
+    fn server_version<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.inner.server_version.as_ref().map(|version| version_dict(py, version)).transpose()
+    }
+
+    /// Returns a dict of 'submit_time', 'start_time' and 'finish_time' in Unix seconds, or `None`
+    /// if the artifact comes from the compile cache
+    #[getter]
+    fn timings<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let timings = match self.inner.timings {
+            Some(timings) => timings,
+            None => return Ok(None),
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("submit_time", timings.submit_time)?;
+        dict.set_item("start_time", timings.start_time)?;
+        dict.set_item("finish_time", timings.finish_time)?;
+        Ok(Some(dict))
+    }
+
+    #[getter]
+    fn input_hash(&self) -> Option<&str> {
+        self.inner.input_hash.as_deref()
+    }
+
+    /// Returns SHA-256 of the artifact in hex
+    fn sha256(&self) -> String {
+        self.inner.sha256()
+    }
+}
+
+fn version_dict<'py>(py: Python<'py>, version: &VersionInfo) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("version", &version.version)?;
+    dict.set_item("revision", &version.revision)?;
+    dict.set_item("build_time", &version.build_time)?;
+    Ok(dict)
+}
+
+#[pyclass(name = "FuriosaClient")]
+struct PyFuriosaClient {
+    inner: Arc<furiosa_client::FuriosaClient>,
+}
+
+/// Blocks on the runtime of pyo3-async-runtimes with the GIL released
+fn block_on<F, T>(py: Python<'_>, future: F) -> PyResult<T>
+where
+    F: Future<Output = Result<T, ClientError>> + Send,
+    T: Send,
+{
+    py.allow_threads(|| pyo3_async_runtimes::tokio::get_runtime().block_on(future))
+        .map_err(to_py_err)
+}
+
+/// Returns an awaitable of the artifact
+fn artifact_future<F>(py: Python<'_>, future: F) -> PyResult<Bound<'_, PyAny>>
+where
+    F: Future<Output = Result<Box<[u8]>, ClientError>> + Send + 'static,
+{
+    pyo3_async_runtimes::tokio::future_into_py(py, async move {
+        let artifact = future.await.map_err(to_py_err)?;
+        Ok(Python::with_gil(|py| PyBytes::new(py, &artifact).unbind()))
+    })
+}
+
+#[pymethods]
+impl PyFuriosaClient {
+    /// Reads the environment variables and `$HOME/.furiosa` unless the endpoint and the API keys
+    /// are given
+    #[new]
+    #[pyo3(signature = (runtime_version, endpoint=None, access_key_id=None, secret_access_key=None))]
+    fn new(
+        runtime_version: &str,
+        endpoint: Option<String>,
+        access_key_id: Option<String>,
+        secret_access_key: Option<String>,
+    ) -> PyResult<PyFuriosaClient> {
+        let client = match (endpoint, access_key_id, secret_access_key) {
+            (Some(endpoint), Some(key), Some(secret)) => {
+                furiosa_client::FuriosaClient::with_credential(
+                    runtime_version,
+                    endpoint,
+                    key,
+                    secret,
+                )
+                .map_err(to_py_err)?
+            }
+            (None, None, None) => {
+                furiosa_client::FuriosaClient::new(runtime_version).map_err(to_py_err)?
+            }
+            _ => {
+                return Err(exceptions::ClientError::new_err(
+                    "endpoint, access_key_id and secret_access_key must be given together",
+                ))
+            }
+        };
+        Ok(PyFuriosaClient { inner: Arc::new(client) })
+    }
+
+    #[getter]
+    fn endpoint(&self) -> &str {
+        self.inner.endpoint()
+    }
+
+    /// Returns a dict of 'version', 'revision' and 'build_time'
+    fn server_version<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let version = block_on(py, self.inner.server_version())?;
+        version_dict(py, &version)
+    }
+
+    fn compile(&self, py: Python<'_>, request: &PyCompileRequest) -> PyResult<PyCompiledModel> {
+        let inner = block_on(py, self.inner.compile(request.to_request()))?;
+        Ok(PyCompiledModel { inner })
+    }
+
+    /// Returns an awaitable of `CompiledModel`
+    fn compile_async<'py>(
+        &self,
+        py: Python<'py>,
+        request: &PyCompileRequest,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let (client, request) = (self.inner.clone(), request.to_request());
+        pyo3_async_runtimes::tokio::future_into_py(py, async move {
+            Ok(PyCompiledModel { inner: client.compile(request).await.map_err(to_py_err)? })
+        })
+    }
+
+    fn optimize<'py>(
+        &self,
+        py: Python<'py>,
+        request: &PyOptimizeRequest,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let artifact = block_on(py, self.inner.optimize(request.to_request()))?;
+        Ok(PyBytes::new(py, &artifact))
+    }
+
+    fn optimize_async<'py>(
+        &self,
+        py: Python<'py>,
+        request: &PyOptimizeRequest,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let (client, request) = (self.inner.clone(), request.to_request());
+        artifact_future(py, async move { client.optimize(request).await })
+    }
+
+    fn build_calibration_model<'py>(
+        &self,
+        py: Python<'py>,
+        request: &PyCalibrateRequest,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let artifact = block_on(py, self.inner.build_calibration_model(request.to_request()))?;
+        Ok(PyBytes::new(py, &artifact))
+    }
+
+    fn build_calibration_model_async<'py>(
+        &self,
+        py: Python<'py>,
+        request: &PyCalibrateRequest,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let (client, request) = (self.inner.clone(), request.to_request());
+        artifact_future(py, async move { client.build_calibration_model(request).await })
+    }
+
+    fn quantize<'py>(
+        &self,
+        py: Python<'py>,
+        request: &PyQuantizeRequest,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let artifact = block_on(py, self.inner.quantize(request.to_request()))?;
+        Ok(PyBytes::new(py, &artifact))
+    }
+
+    fn quantize_async<'py>(
+        &self,
+        py: Python<'py>,
+        request: &PyQuantizeRequest,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let (client, request) = (self.inner.clone(), request.to_request());
+        artifact_future(py, async move { client.quantize(request).await })
+    }
+}
+
+/// The `furiosa_client` module, which can also be registered by `pyo3::append_to_inittab!` to
+/// embed it
+#[pymodule]
+#[pyo3(name = "furiosa_client")]
+pub fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add_class::<PyFuriosaClient>()?;
+    m.add_class::<PyCompiledModel>()?;
+    m.add_class::<PyCompileRequest>()?;
+    m.add_class::<PyOptimizeRequest>()?;
+    m.add_class::<PyCalibrateRequest>()?;
+    m.add_class::<PyQuantizeRequest>()?;
+    m.add_class::<PyTargetIr>()?;
+    m.add("ClientError", py.get_type::<exceptions::ClientError>())?;
+    m.add("ApiError", py.get_type::<exceptions::ApiError>())?;
+    m.add("CompilationFailed", py.get_type::<exceptions::CompilationFailed>())?;
+    m.add("NoApiKey", py.get_type::<exceptions::NoApiKey>())?;
+    m.add("ConfigError", py.get_type::<exceptions::ConfigError>())?;
+    m.add("InvalidRuntimeVersion", py.get_type::<exceptions::InvalidRuntimeVersion>())?;
+    m.add("Unsupported", py.get_type::<exceptions::Unsupported>())?;
+    m.add("IncompatibleServer", py.get_type::<exceptions::IncompatibleServer>())?;
+    m.add("InvalidTargetIr", py.get_type::<exceptions::InvalidTargetIr>())?;
+    m.add("InvalidModel", py.get_type::<exceptions::InvalidModel>())?;
+    m.add("InvalidLabel", py.get_type::<exceptions::InvalidLabel>())?;
+    m.add("DownloadError", py.get_type::<exceptions::DownloadError>())?;
+    m.add("UnsupportedNpuSpec", py.get_type::<exceptions::UnsupportedNpuSpec>())?;
+    m.add("RateLimited", py.get_type::<exceptions::RateLimited>())?;
+    Ok(())
+}
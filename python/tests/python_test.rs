@@ -0,0 +1,25 @@
+use std::ffi::CString;
+
+use furiosa_client::testing::{CompileScript, MockResponse, MockServer};
+use furiosa_client_python::python_module;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+/// Runs Python code with the module against the mock server
+#[test]
+fn test_python() -> PyResult<()> {
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+    server.script_compile(CompileScript::succeed(b"ENF2".to_vec()));
+    server.script_compile(CompileScript::fail("unsupported operator"));
+    server.respond_dss("quantize", MockResponse::bytes(b"QUANTIZED".to_vec()));
+
+    pyo3::append_to_inittab!(python_module);
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let globals = PyDict::new(py);
+        globals.set_item("endpoint", server.endpoint())?;
+        let code = CString::new(include_str!("test_client.py")).unwrap();
+        py.run(&code, Some(&globals), None)
+    })
+}
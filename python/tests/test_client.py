@@ -0,0 +1,60 @@
+# Runs with `endpoint` of the mock server given by python_test.rs
+import asyncio
+import os
+import tempfile
+
+import furiosa_client as fc
+
+client = fc.FuriosaClient("0.4.0", endpoint, "mock-key", "mock-secret")
+assert client.endpoint == endpoint
+
+request = fc.CompileRequest({"npu": "64dpes"}, b"model", target_ir=fc.TargetIr.Enf, labels={"team": "vision"})
+assert request.target_ir == fc.TargetIr.Enf
+assert str(fc.TargetIr.parse("LIR")) == "lir"
+compiled = client.compile(request)
+assert bytes(compiled) == compiled.bytes == b"ENF"
+assert compiled.target_ir == fc.TargetIr.Enf
+assert compiled.artifacts == {"enf": b"ENF"}
+assert compiled.labels == {"team": "vision"}
+assert compiled.runtime_version == "0.4.0"
+assert compiled.task_id is not None
+assert compiled.input_hash is not None
+assert compiled.timings["submit_time"] > 0
+
+with tempfile.TemporaryDirectory() as tmp:
+    path = os.path.join(tmp, "model.enf")
+    compiled.save(path)
+    assert os.path.exists(path + ".manifest.json")
+    loaded = fc.CompiledModel.load(path)
+    assert loaded.bytes == b"ENF"
+    assert loaded.task_id == compiled.task_id
+    assert loaded.sha256() == compiled.sha256()
+
+
+async def compile_async():
+    return await client.compile_async(request)
+
+
+assert asyncio.run(compile_async()).bytes == b"ENF2"
+
+try:
+    client.compile(request)
+    raise AssertionError("compilation must fail")
+except fc.CompilationFailed as e:
+    assert isinstance(e, fc.ClientError)
+
+try:
+    fc.TargetIr.parse("xyz")
+    raise AssertionError("parsing must fail")
+except fc.InvalidTargetIr:
+    pass
+
+quantize = fc.QuantizeRequest(b"model", {"input": (0.0, 1.0)}, input_tensors=["input"])
+assert quantize.input_tensors == ["input"]
+assert client.quantize(quantize) == b"QUANTIZED"
+
+try:
+    client.optimize(fc.OptimizeRequest(b"model"))
+    raise AssertionError("optimize must fail")
+except fc.ApiError:
+    pass
@@ -0,0 +1,310 @@
+//! Compiled artifacts with their provenance
+//!
+//! `CompiledModel` keeps which inputs, compiler and task produced an artifact.
+//! `CompiledModel::save` writes the artifact with a sidecar manifest (`<path>.manifest.json`),
+//! which `CompiledModel::load` reads back after verifying the artifact checksum. The artifacts
+//! of extra IRs are written next to it, e.g., `model.gir` for `model.enf`.
+//! `CompileOutput` decodes an artifact by its IR.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{ClientError, CompileTask, Labels, TargetIr, VersionInfo};
+
+static MANIFEST_EXTENSION: &str = "manifest.json";
+
+/// Unix timestamps of a compile task in seconds
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CompileTimings {
+    pub submit_time: i64,
+    pub start_time: Option<i64>,
+    pub finish_time: Option<i64>,
+}
+
+impl From<&CompileTask> for CompileTimings {
+    fn from(task: &CompileTask) -> Self {
+        CompileTimings {
+            submit_time: task.submit_time,
+            start_time: task.start_time,
+            finish_time: task.finish_time,
+        }
+    }
+}
+
+/// An artifact decoded by its IR
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum CompileOutput {
+    /// A textual IR dump
+    Text(String),
+    /// ENF, or a textual IR which isn't valid UTF-8
+    Binary(Box<[u8]>),
+}
+
+impl CompileOutput {
+    /// Decodes the artifact of `target_ir` into `Text` if the IR is textual, otherwise keeps
+    /// the bytes. An invalid UTF-8 dump is kept as bytes instead of failing.
+    pub fn decode(target_ir: TargetIr, bytes: Box<[u8]>) -> CompileOutput {
+        if !target_ir.is_textual() {
+            return CompileOutput::Binary(bytes);
+        }
+        match String::from_utf8(bytes.into_vec()) {
+            Ok(text) => CompileOutput::Text(text),
+            Err(e) => CompileOutput::Binary(e.into_bytes().into_boxed_slice()),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            CompileOutput::Text(text) => text.as_bytes(),
+            CompileOutput::Binary(bytes) => bytes,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            CompileOutput::Text(text) => Some(text),
+            CompileOutput::Binary(_) => None,
+        }
+    }
+
+    pub fn into_bytes(self) -> Box<[u8]> {
+        match self {
+            CompileOutput::Text(text) => text.into_bytes().into_boxed_slice(),
+            CompileOutput::Binary(bytes) => bytes,
+        }
+    }
+}
+
+/// An artifact returned by `FuriosaClient::compile`, which dereferences to its bytes
+#[derive(Debug, Clone)]
+pub struct CompiledModel {
+    pub bytes: Box<[u8]>,
+    pub target_ir: TargetIr,
+    /// Artifacts of `CompileRequest::extra_target_irs`
+    pub extra_artifacts: BTreeMap<TargetIr, Box<[u8]>>,
+    /// File name of the source model
+    pub filename: String,
+    pub labels: Labels,
+    pub runtime_version: String,
+    /// `None` if the artifact comes from the compile cache
+    pub task_id: Option<String>,
+    /// `None` if the server version couldn't be fetched
+    pub server_version: Option<VersionInfo>,
+    /// `None` if the artifact comes from the compile cache
+    pub timings: Option<CompileTimings>,
+    /// SHA-256 of the model, the NPU spec, the compiler config, the target IR and the runtime
+    /// version, which is also the key of the compile cache. `None` if the source couldn't be
+    /// hashed while uploading, e.g., when the server responded before reading all of it.
+    pub input_hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArtifactEntry {
+    /// File name of the artifact when it was saved
+    artifact: String,
+    size: u64,
+    sha256: String,
+}
+
+impl ArtifactEntry {
+    fn new(path: &Path, bytes: &[u8]) -> ArtifactEntry {
+        ArtifactEntry {
+            artifact: crate::source::file_name(path),
+            size: bytes.len() as u64,
+            sha256: format!("{:x}", Sha256::digest(bytes)),
+        }
+    }
+
+    /// Reads the artifact at `path` and verifies its checksum
+    fn read(&self, path: &Path) -> Result<Box<[u8]>, ClientError> {
+        let bytes = fs::read(path)?;
+        let sha256 = format!("{:x}", Sha256::digest(&bytes));
+        if sha256 != self.sha256 {
+            return Err(ClientError::ChecksumMismatch(self.sha256.clone(), sha256));
+        }
+        Ok(bytes.into_boxed_slice())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    client_version: String,
+    #[serde(flatten)]
+    artifact: ArtifactEntry,
+    target_ir: TargetIr,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    extra_artifacts: BTreeMap<TargetIr, ArtifactEntry>,
+    filename: String,
+    #[serde(default)]
+    labels: Labels,
+    runtime_version: String,
+    task_id: Option<String>,
+    server_version: Option<VersionInfo>,
+    timings: Option<CompileTimings>,
+    input_hash: Option<String>,
+}
+
+impl CompiledModel {
+    pub fn into_bytes(self) -> Box<[u8]> {
+        self.bytes
+    }
+
+    pub fn into_output(self) -> CompileOutput {
+        CompileOutput::decode(self.target_ir, self.bytes)
+    }
+
+    /// Returns the artifact of `target_ir` if it was requested
+    pub fn artifact(&self, target_ir: TargetIr) -> Option<&[u8]> {
+        if target_ir == self.target_ir {
+            Some(&self.bytes)
+        } else {
+            self.extra_artifacts.get(&target_ir).map(|bytes| &bytes[..])
+        }
+    }
+
+    /// Decodes the artifact of `target_ir` if it was requested
+    pub fn output(&self, target_ir: TargetIr) -> Option<CompileOutput> {
+        let bytes = self.artifact(target_ir)?;
+        Some(CompileOutput::decode(target_ir, bytes.to_vec().into_boxed_slice()))
+    }
+
+    /// Sets the artifacts in the order of `CompileRequest::requested_target_irs`. Fails with
+    /// `ClientError::ApiError` if the first one isn't the artifact of `target_ir`.
+    pub(crate) fn set_artifacts(
+        &mut self,
+        artifacts: Vec<(TargetIr, Box<[u8]>)>,
+    ) -> Result<(), ClientError> {
+        let mut artifacts = artifacts.into_iter();
+        match artifacts.next() {
+            Some((target_ir, bytes)) if target_ir == self.target_ir => self.bytes = bytes,
+            _ => return Err(ClientError::ApiError(format!("no artifact of {}", self.target_ir))),
+        }
+        self.extra_artifacts = artifacts.collect();
+        Ok(())
+    }
+
+    /// Returns SHA-256 of the artifact in hex
+    pub fn sha256(&self) -> String {
+        format!("{:x}", Sha256::digest(&self.bytes))
+    }
+
+    /// Returns the path of the manifest written with the artifact at `path`
+    pub fn manifest_path<P: AsRef<Path>>(path: P) -> PathBuf {
+        let mut file_name = path.as_ref().file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(MANIFEST_EXTENSION);
+        path.as_ref().with_file_name(file_name)
+    }
+
+    /// Returns the path of the artifact of an extra IR written with the artifact at `path`
+    pub fn extra_artifact_path<P: AsRef<Path>>(path: P, target_ir: TargetIr) -> PathBuf {
+        path.as_ref().with_extension(target_ir.as_str())
+    }
+
+    /// Writes the artifact into `path`, the artifacts of extra IRs into `extra_artifact_path`
+    /// and the manifest into `manifest_path(path)`. Every file is written into `<file>.tmp`
+    /// first, and renamed once all of them are written, the manifest last, so that a failed
+    /// save doesn't leave a truncated artifact or a manifest of missing artifacts.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ClientError> {
+        let path = path.as_ref();
+        let mut files = Vec::new();
+        let mut extra_artifacts = BTreeMap::new();
+        for (&target_ir, bytes) in &self.extra_artifacts {
+            let extra_path = CompiledModel::extra_artifact_path(path, target_ir);
+            if extra_path == path {
+                let msg = format!("{} would be overwritten by {}", path.display(), target_ir);
+                return Err(ClientError::io_error(std::io::ErrorKind::InvalidInput, &msg));
+            }
+            extra_artifacts.insert(target_ir, ArtifactEntry::new(&extra_path, bytes));
+            files.push((extra_path, bytes.to_vec()));
+        }
+        let manifest = Manifest {
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            artifact: ArtifactEntry::new(path, &self.bytes),
+            target_ir: self.target_ir,
+            extra_artifacts,
+            filename: self.filename.clone(),
+            labels: self.labels.clone(),
+            runtime_version: self.runtime_version.clone(),
+            task_id: self.task_id.clone(),
+            server_version: self.server_version.clone(),
+            timings: self.timings,
+            input_hash: self.input_hash.clone(),
+        };
+        let json = serde_json::to_vec_pretty(&manifest).expect("fail to serialize the manifest");
+        files.push((path.to_path_buf(), self.bytes.to_vec()));
+        files.push((CompiledModel::manifest_path(path), json));
+
+        let mut tmp_paths = Vec::with_capacity(files.len());
+        for (file, bytes) in &files {
+            tmp_paths.push(tmp_path(file));
+            if let Err(e) = fs::write(tmp_paths.last().unwrap(), bytes) {
+                for tmp_path in &tmp_paths {
+                    let _ = fs::remove_file(tmp_path);
+                }
+                return Err(e.into());
+            }
+        }
+        for (tmp_path, (file, _)) in tmp_paths.iter().zip(&files) {
+            fs::rename(tmp_path, file)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the artifact at `path`, the artifacts of extra IRs at `extra_artifact_path` and
+    /// the manifest at `manifest_path(path)`. Fails with `ClientError::ChecksumMismatch` if an
+    /// artifact doesn't match the manifest.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<CompiledModel, ClientError> {
+        let path = path.as_ref();
+        let manifest_path = CompiledModel::manifest_path(path);
+        let manifest: Manifest =
+            serde_json::from_slice(&fs::read(&manifest_path)?).map_err(|e| {
+                let msg = format!("fail to parse {}: {}", manifest_path.display(), e);
+                ClientError::io_error(std::io::ErrorKind::InvalidData, &msg)
+            })?;
+        // the artifacts are found by the naming of `save`, so that they can be renamed together
+        let mut extra_artifacts = BTreeMap::new();
+        for (&target_ir, entry) in &manifest.extra_artifacts {
+            let extra_path = CompiledModel::extra_artifact_path(path, target_ir);
+            extra_artifacts.insert(target_ir, entry.read(&extra_path)?);
+        }
+        Ok(CompiledModel {
+            bytes: manifest.artifact.read(path)?,
+            target_ir: manifest.target_ir,
+            extra_artifacts,
+            filename: manifest.filename,
+            labels: manifest.labels,
+            runtime_version: manifest.runtime_version,
+            task_id: manifest.task_id,
+            server_version: manifest.server_version,
+            timings: manifest.timings,
+            input_hash: manifest.input_hash,
+        })
+    }
+}
+
+/// Returns `<path>.tmp`, which is renamed to `path` once written
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".tmp");
+    path.with_file_name(file_name)
+}
+
+impl Deref for CompiledModel {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl AsRef<[u8]> for CompiledModel {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
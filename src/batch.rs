@@ -0,0 +1,179 @@
+//! Compiling many models with bounded concurrency
+//!
+//! `FuriosaClient::compile_many` runs `compile` for each request, at most `concurrency` at
+//! once over the connection pool of the client, and yields the results in completion order
+//! with the indices of the requests. In `FailFast` mode, the tasks submitted by the dropped
+//! compilations are cancelled.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream, StreamExt};
+use tracing::{info, warn};
+
+use crate::{ratelimit, ClientError, CompileRequest, CompiledModel, FuriosaClient, Metric};
+
+static DEFAULT_RETRY_DELAY_MS: u64 = 1000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BatchMode {
+    /// Stops after the first failure, dropping the compilations in progress and cancelling
+    /// their tasks
+    FailFast,
+    /// Compiles all requests regardless of failures
+    CollectAll,
+}
+
+#[derive(Clone, Debug)]
+pub struct BatchOptions {
+    concurrency: usize,
+    retries: usize,
+    retry_delay: Duration,
+    mode: BatchMode,
+}
+
+impl BatchOptions {
+    /// Compiles at most `concurrency` requests at once without retries in `CollectAll` mode
+    pub fn new(concurrency: usize) -> BatchOptions {
+        BatchOptions {
+            concurrency: concurrency.max(1),
+            retries: 0,
+            retry_delay: Duration::from_millis(DEFAULT_RETRY_DELAY_MS),
+            mode: BatchMode::CollectAll,
+        }
+    }
+
+    /// Retries each request up to `retries` times if it fails with a 5xx status, a transport
+    /// error including timeouts, an interrupted download or a rate limit.
+    /// Requests streaming from readers are never retried.
+    pub fn retries(mut self, retries: usize) -> BatchOptions {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the delay before the first retry, which doubles on each retry (1s by default)
+    pub fn retry_delay(mut self, delay: Duration) -> BatchOptions {
+        self.retry_delay = delay;
+        self
+    }
+
+    pub fn mode(mut self, mode: BatchMode) -> BatchOptions {
+        self.mode = mode;
+        self
+    }
+}
+
+/// Errors which may succeed on another attempt. Other API errors are 4xx responses, which
+/// fail in the same way again, and rate limits longer than `MAX_RETRY_AFTER` aren't waited for.
+fn is_retryable(e: &ClientError) -> bool {
+    match e {
+        ClientError::RateLimited(_, retry_after) => ratelimit::retry_wait(*retry_after).is_some(),
+        _ => matches!(
+            e,
+            ClientError::ServerError(..) | ClientError::Transport(_) | ClientError::Download(_)
+        ),
+    }
+}
+
+/// IDs of the compile tasks which have been submitted but not completed
+#[derive(Default)]
+pub(crate) struct SubmittedTasks(Mutex<HashSet<String>>);
+
+impl SubmittedTasks {
+    pub(crate) fn insert(&self, task_id: &str) {
+        self.0.lock().unwrap().insert(task_id.to_string());
+    }
+
+    pub(crate) fn remove(&self, task_id: &str) {
+        self.0.lock().unwrap().remove(task_id);
+    }
+
+    fn take(&self) -> Vec<String> {
+        self.0.lock().unwrap().drain().collect()
+    }
+}
+
+impl FuriosaClient {
+    /// Compiles the requests and yields `(index, result)` in completion order, where `index` is
+    /// the position of the request in `requests`
+    pub fn compile_many<I>(
+        &self,
+        requests: I,
+        options: BatchOptions,
+    ) -> impl Stream<Item = (usize, Result<CompiledModel, ClientError>)> + '_
+    where
+        I: IntoIterator<Item = CompileRequest>,
+    {
+        let requests: Vec<CompileRequest> = requests.into_iter().collect();
+        let fail_fast = options.mode == BatchMode::FailFast;
+        let concurrency = options.concurrency;
+        let submitted = Arc::new(SubmittedTasks::default());
+        let tracked = submitted.clone();
+        stream::iter(requests.into_iter().enumerate())
+            .map(move |(index, request)| {
+                let options = options.clone();
+                let submitted = tracked.clone();
+                async move {
+                    (index, self.compile_with_retries(request, &options, &submitted).await)
+                }
+            })
+            .buffer_unordered(concurrency)
+            .scan(false, move |failed, (index, result)| {
+                let stopped = *failed;
+                *failed = *failed || (fail_fast && result.is_err());
+                let submitted = if *failed { Some(submitted.clone()) } else { None };
+                async move {
+                    // the compilations in progress are never polled again once it fails, so
+                    // their tasks are cancelled, including ones submitted after the failure
+                    if let Some(submitted) = submitted {
+                        self.cancel_submitted(&submitted).await;
+                    }
+                    if stopped {
+                        None
+                    } else {
+                        Some((index, result))
+                    }
+                }
+            })
+    }
+
+    async fn cancel_submitted(&self, submitted: &SubmittedTasks) {
+        for task_id in submitted.take() {
+            match self.cancel_task(&task_id).await {
+                Ok(()) => info!(task_id = task_id.as_str(), "cancelled the compile task"),
+                Err(e) => warn!(task_id = task_id.as_str(), error = %e, "fail to cancel"),
+            }
+        }
+    }
+
+    async fn compile_with_retries(
+        &self,
+        request: CompileRequest,
+        options: &BatchOptions,
+        submitted: &SubmittedTasks,
+    ) -> Result<CompiledModel, ClientError> {
+        let mut delay = options.retry_delay;
+        let mut attempt = 0;
+        let mut request = request;
+        loop {
+            let retry = if attempt < options.retries { request.try_clone() } else { None };
+            match (self.compile_tracked(request, Some(submitted)).await, retry) {
+                (Err(e), Some(retry)) if is_retryable(&e) => {
+                    attempt += 1;
+                    warn!(attempt, error = %e, "retrying the compilation");
+                    self.record_metric(Metric::Retry { operation: "compile" });
+                    match e {
+                        ClientError::RateLimited(_, Some(after)) => {
+                            tokio::time::sleep(after.max(delay)).await
+                        }
+                        _ => tokio::time::sleep(delay).await,
+                    }
+                    delay *= 2;
+                    request = retry;
+                }
+                (result, _) => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! Command line interface of Furiosa API
+//!
+//! ```sh
+//! furiosa compile model.tflite --npu-spec configs/64dpes.yml -o output.enf
+//! furiosa tasks list --format json
+//! ```
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+use serde_json::Value;
+use structopt::StructOpt;
+
+use furiosa_client::{
+    CalibrateRequest, ClientError, Compatibility, CompileRequest, CompileTask, CompileTaskPhase,
+    FuriosaClient, OptimizeRequest, Profile, QuantizeRequest, ResolvedConfig, TargetIr, TaskFilter,
+    TaskPage, VersionInfo, FURIOSA_PROFILE_ENV,
+};
+
+#[derive(StructOpt)]
+#[structopt(name = "furiosa", about = "Command line interface of Furiosa API")]
+struct Opt {
+    /// Output format: 'text' or 'json'
+    #[structopt(long, global = true, default_value = "text", possible_values = &["text", "json"])]
+    format: String,
+
+    /// SDK version sent to the server
+    #[structopt(long, global = true, default_value = env!("CARGO_PKG_VERSION"))]
+    runtime_version: String,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Compiles a model for the NPU
+    Compile {
+        /// ONNX or TFLite model
+        model: PathBuf,
+        /// YAML file of the target NPU spec
+        #[structopt(long)]
+        npu_spec: PathBuf,
+        /// YAML or JSON file of the compiler config
+        #[structopt(long)]
+        config: Option<PathBuf>,
+        /// One of dfg, ldfg, cdfg, gir, lir and enf, whose artifact is written into the output
+        #[structopt(long, default_value = "enf")]
+        target_ir: TargetIr,
+        /// IR whose artifact is also written next to the output from the same task (e.g.,
+        /// output.gir), which can be given more than once
+        #[structopt(long)]
+        extra_target_ir: Vec<TargetIr>,
+        /// Label of the request, given as KEY=VALUE
+        #[structopt(long)]
+        label: Vec<Label>,
+        /// output.<target IR> by default
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Optimizes an ONNX model for quantization
+    Optimize {
+        model: PathBuf,
+        /// Label of the request, given as KEY=VALUE
+        #[structopt(long)]
+        label: Vec<Label>,
+        #[structopt(short, long, default_value = "optimized.onnx")]
+        output: PathBuf,
+    },
+    /// Builds a calibration model from an ONNX model
+    Calibrate {
+        model: PathBuf,
+        /// Input tensor names. The graph inputs are used if not given.
+        #[structopt(long)]
+        input_tensors: Vec<String>,
+        /// Label of the request, given as KEY=VALUE
+        #[structopt(long)]
+        label: Vec<Label>,
+        #[structopt(short, long, default_value = "calibration.onnx")]
+        output: PathBuf,
+    },
+    /// Quantizes an ONNX model with dynamic ranges
+    Quantize {
+        model: PathBuf,
+        /// JSON file of dynamic ranges of tensors
+        #[structopt(long)]
+        dynamic_ranges: PathBuf,
+        /// Input tensor names. The graph inputs are used if not given.
+        #[structopt(long)]
+        input_tensors: Vec<String>,
+        /// Label of the request, given as KEY=VALUE
+        #[structopt(long)]
+        label: Vec<Label>,
+        #[structopt(short, long, default_value = "quantized.onnx")]
+        output: PathBuf,
+    },
+    /// Shows the client and server versions
+    Version,
+    /// Manages compile tasks
+    Tasks(TasksCommand),
+    /// Prompts for API keys and writes them to $HOME/.furiosa
+    Configure {
+        /// Profile to be written. FURIOSA_PROFILE or 'default' is used if not given.
+        #[structopt(long)]
+        profile: Option<String>,
+        /// Skips prompting for the access key ID
+        #[structopt(long)]
+        access_key_id: Option<String>,
+        /// Skips prompting for the secret access key
+        #[structopt(long)]
+        secret_access_key: Option<String>,
+        /// Skips prompting for the API endpoint
+        #[structopt(long)]
+        endpoint: Option<String>,
+        /// Writes the files without checking the API keys against the server
+        #[structopt(long)]
+        no_verify: bool,
+        /// Shows the current configuration with masked API keys instead
+        #[structopt(long)]
+        show: bool,
+    },
+}
+
+/// A label given as KEY=VALUE
+struct Label(String, String);
+
+impl FromStr for Label {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut kv = s.splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some(key), Some(value)) if !key.is_empty() => {
+                Ok(Label(key.to_string(), value.to_string()))
+            }
+            _ => Err(format!("'{}' is not KEY=VALUE", s)),
+        }
+    }
+}
+
+#[derive(StructOpt)]
+enum TasksCommand {
+    /// Lists compile tasks
+    List {
+        /// Lists only tasks in the phases (pending, running, succeeded or failed)
+        #[structopt(long)]
+        phase: Vec<CompileTaskPhase>,
+        /// Lists only tasks submitted at or after the unix timestamp
+        #[structopt(long)]
+        since: Option<i64>,
+        /// Lists only tasks submitted before the unix timestamp
+        #[structopt(long)]
+        until: Option<i64>,
+        /// Lists only tasks with the label, given as KEY=VALUE
+        #[structopt(long)]
+        label: Vec<Label>,
+        /// Number of tasks in a page
+        #[structopt(long)]
+        page_size: Option<u32>,
+        /// Token of the page printed by the previous command
+        #[structopt(long)]
+        page_token: Option<String>,
+        /// Lists all pages
+        #[structopt(long)]
+        all: bool,
+    },
+    /// Shows a compile task
+    Get { task_id: String },
+    /// Cancels a compile task
+    Cancel { task_id: String },
+    /// Prints the logs of a compile task
+    Logs { task_id: String },
+    /// Downloads the artifact of a compile task which has succeeded
+    Artifact {
+        task_id: String,
+        /// One of dfg, ldfg, cdfg, gir, lir and enf
+        #[structopt(long, default_value = "enf")]
+        target_ir: TargetIr,
+        /// output.<target IR> by default
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Serialize)]
+struct ArtifactOutput {
+    output: PathBuf,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct VersionOutput {
+    client: String,
+    server: Option<VersionInfo>,
+    compatibility: Option<Compatibility>,
+}
+
+#[tokio::main]
+async fn main() {
+    let opt = Opt::from_args();
+    if let Err(e) = run(opt).await {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(opt: Opt) -> Result<(), ClientError> {
+    let json = opt.format == "json";
+    match opt.command {
+        Command::Compile { model, npu_spec, config, target_ir, extra_target_ir, label, output } => {
+            let client = FuriosaClient::new(&opt.runtime_version)?;
+            let mut request = CompileRequest::from_path(read_yaml(&npu_spec)?, &model)
+                .target_irs(target_ir, extra_target_ir);
+            if let Some(config) = config {
+                request = request.compile_config(read_yaml(&config)?);
+            }
+            for Label(key, value) in label {
+                request = request.label(&key, &value);
+            }
+            let output = output.unwrap_or_else(|| request.target_ir.artifact_name().into());
+            let size = client.compile_to_path(request, &output).await?;
+            print_artifact(json, output, size);
+        }
+        Command::Optimize { model, label, output } => {
+            let client = FuriosaClient::new(&opt.runtime_version)?;
+            let mut request = OptimizeRequest::from_path(&model);
+            for Label(key, value) in label {
+                request = request.label(&key, &value);
+            }
+            let size = client.optimize_to_path(request, &output).await?;
+            print_artifact(json, output, size);
+        }
+        Command::Calibrate { model, input_tensors, label, output } => {
+            let client = FuriosaClient::new(&opt.runtime_version)?;
+            let mut request = CalibrateRequest::from_path(&model)?;
+            if !input_tensors.is_empty() {
+                request = request.input_tensors(input_tensors);
+            }
+            for Label(key, value) in label {
+                request = request.label(&key, &value);
+            }
+            let size = client.build_calibration_model_to_path(request, &output).await?;
+            print_artifact(json, output, size);
+        }
+        Command::Quantize { model, dynamic_ranges, input_tensors, label, output } => {
+            let client = FuriosaClient::new(&opt.runtime_version)?;
+            let dynamic_ranges: HashMap<String, (f32, f32)> =
+                serde_json::from_slice(&std::fs::read(&dynamic_ranges)?)
+                    .map_err(|e| invalid_input(&dynamic_ranges, e))?;
+            let mut request = QuantizeRequest::from_path(&model, dynamic_ranges)?;
+            if !input_tensors.is_empty() {
+                request = request.input_tensors(input_tensors);
+            }
+            for Label(key, value) in label {
+                request = request.label(&key, &value);
+            }
+            let size = client.quantize_to_path(request, &output).await?;
+            print_artifact(json, output, size);
+        }
+        Command::Version => {
+            let server = match FuriosaClient::new(&opt.runtime_version) {
+                Ok(client) => Some(client.server_version().await?),
+                Err(ClientError::NoApiKey) => None,
+                Err(e) => return Err(e),
+            };
+            let compatibility = match &server {
+                Some(server) => Some(Compatibility::check(&opt.runtime_version, &server.version)?),
+                None => None,
+            };
+            let version = VersionOutput {
+                client: env!("CARGO_PKG_VERSION").to_string(),
+                server,
+                compatibility,
+            };
+            if json {
+                print_json(&version);
+            } else {
+                println!("client: {}", version.client);
+                if let Some(server) = version.server {
+                    println!(
+                        "server: {} (revision: {}, built at {})",
+                        server.version, server.revision, server.build_time
+                    );
+                }
+                if let Some(compatibility) = version.compatibility {
+                    println!("compatibility: {:?}", compatibility.status);
+                }
+            }
+        }
+        Command::Tasks(command) => {
+            let client = FuriosaClient::new(&opt.runtime_version)?;
+            match command {
+                TasksCommand::List { phase, since, until, label, page_size, page_token, all } => {
+                    let mut filter = TaskFilter::new();
+                    filter.phases = phase;
+                    filter.submitted_after = since;
+                    filter.submitted_before = until;
+                    filter.page_size = page_size;
+                    filter.page_token = page_token;
+                    for Label(key, value) in label {
+                        filter = filter.label(&key, &value);
+                    }
+                    let page = if all {
+                        TaskPage {
+                            tasks: client.list_all_tasks(filter).await?,
+                            next_page_token: None,
+                        }
+                    } else {
+                        client.list_tasks(filter).await?
+                    };
+                    if json {
+                        print_json(&page);
+                    } else {
+                        println!(
+                            "{:<36}  {:<9}  {:>8}  SUBMIT_TIME",
+                            "TASK_ID", "PHASE", "PROGRESS"
+                        );
+                        page.tasks.iter().for_each(print_task);
+                        if let Some(token) = &page.next_page_token {
+                            println!("next page: --page-token {}", token);
+                        }
+                    }
+                }
+                TasksCommand::Get { task_id } => {
+                    let task = client.get_task(&task_id).await?;
+                    if json {
+                        print_json(&task);
+                    } else {
+                        print_task(&task);
+                        if let Some(msg) = &task.error_message {
+                            println!("error: {}", msg);
+                        }
+                    }
+                }
+                TasksCommand::Cancel { task_id } => {
+                    client.cancel_task(&task_id).await?;
+                    if json {
+                        print_json(&serde_json::json!({ "task_id": task_id, "cancelled": true }));
+                    } else {
+                        println!("cancelled {}", task_id);
+                    }
+                }
+                TasksCommand::Artifact { task_id, target_ir, output } => {
+                    let output = output.unwrap_or_else(|| target_ir.artifact_name().into());
+                    let size = client.task_artifact_to_path(&task_id, target_ir, &output).await?;
+                    print_artifact(json, output, size);
+                }
+                TasksCommand::Logs { task_id } => {
+                    let logs = client.task_logs(&task_id).await?;
+                    if json {
+                        print_json(&serde_json::json!({ "task_id": task_id, "logs": logs }));
+                    } else {
+                        print!("{}", logs);
+                    }
+                }
+            }
+        }
+        Command::Configure {
+            profile,
+            access_key_id,
+            secret_access_key,
+            endpoint,
+            no_verify,
+            show,
+        } => {
+            if let Some(profile) = &profile {
+                std::env::set_var(FURIOSA_PROFILE_ENV, profile);
+            }
+            let current = ResolvedConfig::resolve()?;
+            if show {
+                if json {
+                    print_json(&current.masked());
+                } else {
+                    println!("{}", current);
+                }
+                return Ok(());
+            }
+
+            let masked = current.masked();
+            let access_key_id = match access_key_id {
+                Some(key) => key,
+                None => prompt("Access key ID", masked.access_key_id.as_deref(), false)?
+                    .or(current.access_key_id)
+                    .ok_or(ClientError::NoApiKey)?,
+            };
+            let secret_access_key = match secret_access_key {
+                Some(key) => key,
+                None => prompt("Secret access key", masked.secret_access_key.as_deref(), true)?
+                    .or(current.secret_access_key)
+                    .ok_or(ClientError::NoApiKey)?,
+            };
+            let endpoint = match endpoint {
+                Some(endpoint) => endpoint,
+                None => prompt("API endpoint", Some(&current.endpoint), false)?
+                    .unwrap_or(current.endpoint),
+            };
+            let profile = Profile {
+                name: current.profile,
+                access_key_id,
+                secret_access_key,
+                endpoint: Some(endpoint),
+            };
+
+            if !no_verify {
+                profile.verify(&opt.runtime_version).await?;
+            }
+            let files = profile.save()?;
+            if json {
+                print_json(&serde_json::json!({ "profile": profile.name, "files": files }));
+            } else {
+                for file in files {
+                    println!("wrote {}", file.display());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prompts for a value and returns `None` if the input is empty
+fn prompt(name: &str, current: Option<&str>, secret: bool) -> Result<Option<String>, ClientError> {
+    let prompt = format!("{} [{}]: ", name, current.unwrap_or("none"));
+    let input = if secret {
+        rpassword::read_password_from_tty(Some(&prompt))?
+    } else {
+        print!("{}", prompt);
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        input
+    };
+    let input = input.trim();
+    Ok(if input.is_empty() { None } else { Some(input.to_string()) })
+}
+
+fn read_yaml(path: &Path) -> Result<Value, ClientError> {
+    // YAML is a superset of JSON, so this reads JSON files as well
+    serde_yaml::from_slice(&std::fs::read(path)?).map_err(|e| invalid_input(path, e))
+}
+
+fn invalid_input<E: std::fmt::Display>(path: &Path, e: E) -> ClientError {
+    let msg = format!("fail to parse {}: {}", path.display(), e);
+    ClientError::io_error(std::io::ErrorKind::InvalidData, &msg)
+}
+
+fn print_artifact(json: bool, output: PathBuf, size: u64) {
+    if json {
+        print_json(&ArtifactOutput { output, size });
+    } else {
+        println!("wrote {} ({} bytes)", output.display(), size);
+    }
+}
+
+fn print_task(task: &CompileTask) {
+    println!(
+        "{:<36}  {:<9}  {:>8.2}  {}",
+        task.task_id,
+        format!("{:?}", task.phase),
+        task.progress,
+        task.submit_time
+    );
+}
+
+fn print_json<T: Serialize>(value: &T) {
+    println!("{}", serde_json::to_string_pretty(value).expect("fail to serialize the output"));
+}
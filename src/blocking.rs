@@ -1,32 +1,295 @@
-pub use crate::dss::{CalibrateRequest, QuantizeRequest};
-pub use crate::{ClientError, CompileRequest};
+//! Blocking client which runs the async client on a tokio runtime
+//!
+//! The client owns a runtime unless it's given the handle of an external multi-thread one by
+//! `FuriosaClient::with_runtime`. Its methods block the calling thread, so they can't be called
+//! from a thread running a tokio runtime (e.g., in an async fn or `spawn_blocking`), where they
+//! fail with `ClientError::NestedRuntime` instead of panicking. Use the async client there.
+
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::AsyncWrite;
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+
+pub use crate::dss::{CalibrateRequest, OptimizeRequest, QuantizeRequest};
+pub use crate::{
+    BatchOptions, Capabilities, ClientError, Compatibility, CompileOutput, CompileRequest,
+    CompileTask, CompiledModel, QuotaInfo, TargetIr, TaskFilter, TaskPage, VersionInfo,
+};
+
+/// Index of a request given to `compile_many` and its result
+pub type BatchResult = (usize, Result<CompiledModel, ClientError>);
 
 pub struct FuriosaClient {
-    _runtime: tokio::runtime::Runtime,
-    handle: tokio::runtime::Handle,
+    /// `None` if the client shares an external runtime
+    runtime: Option<Runtime>,
+    handle: Handle,
     inner: super::FuriosaClient,
 }
 
 impl FuriosaClient {
+    /// Creates a client configured by the environment variables and `$HOME/.furiosa` like
+    /// the async client, with its own runtime
     pub fn new<S: AsRef<str>>(runtime_version: S) -> Result<FuriosaClient, ClientError> {
-        let _runtime = tokio::runtime::Runtime::new().expect("fail to create tokio runtime");
-        let handle = _runtime.handle().clone();
+        FuriosaClient::from_async(super::FuriosaClient::new(runtime_version)?)
+    }
+
+    /// Creates a client with the given endpoint and API keys, with its own runtime
+    pub fn with_credential<S, E, K, V>(
+        runtime_version: S,
+        endpoint: E,
+        access_key_id: K,
+        secret_access_key: V,
+    ) -> Result<FuriosaClient, ClientError>
+    where
+        S: AsRef<str>,
+        E: Into<String>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        FuriosaClient::from_async(super::FuriosaClient::with_credential(
+            runtime_version,
+            endpoint,
+            access_key_id,
+            secret_access_key,
+        )?)
+    }
+
+    /// Wraps a configured async client (e.g., with a cache or a transport) with a new runtime
+    pub fn from_async(client: super::FuriosaClient) -> Result<FuriosaClient, ClientError> {
+        let runtime = Runtime::new()?;
+        let handle = runtime.handle().clone();
+        Ok(FuriosaClient { runtime: Some(runtime), handle, inner: client })
+    }
+
+    /// Wraps an async client to run on an external runtime, which must outlive the calls.
+    /// Fails if the runtime is a `current_thread` one, whose IO and timers aren't driven while
+    /// the calling thread is blocked.
+    pub fn with_runtime(
+        client: super::FuriosaClient,
+        handle: Handle,
+    ) -> Result<FuriosaClient, ClientError> {
+        if handle.runtime_flavor() == RuntimeFlavor::CurrentThread {
+            let msg = "the blocking client needs a multi-thread runtime";
+            return Err(ClientError::io_error(std::io::ErrorKind::InvalidInput, msg));
+        }
+        Ok(FuriosaClient { runtime: None, handle, inner: client })
+    }
+
+    /// Returns the async client, e.g., to be used inside the runtime
+    pub fn as_async(&self) -> &super::FuriosaClient {
+        &self.inner
+    }
+
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    fn block_on<F: Future>(&self, future: F) -> Result<F::Output, ClientError> {
+        if Handle::try_current().is_ok() {
+            return Err(ClientError::NestedRuntime);
+        }
+        Ok(self.handle.block_on(future))
+    }
+
+    pub fn endpoint(&self) -> &str {
+        self.inner.endpoint()
+    }
+
+    pub fn quota(&self) -> Option<QuotaInfo> {
+        self.inner.quota()
+    }
+
+    pub fn server_version(&self) -> Result<VersionInfo, ClientError> {
+        self.block_on(self.inner.server_version())?
+    }
 
-        Ok(FuriosaClient { inner: super::FuriosaClient::new(runtime_version)?, _runtime, handle })
+    pub fn capabilities(&self) -> Result<Capabilities, ClientError> {
+        self.block_on(self.inner.capabilities())?
     }
 
-    pub fn compile(&self, request: CompileRequest) -> Result<Box<[u8]>, ClientError> {
-        self.handle.block_on(self.inner.compile(request))
+    pub fn discover_capabilities(mut self) -> Result<FuriosaClient, ClientError> {
+        self.inner.capabilities = Some(self.capabilities()?);
+        Ok(self)
+    }
+
+    pub fn discovered_capabilities(&self) -> Option<&Capabilities> {
+        self.inner.discovered_capabilities()
+    }
+
+    pub fn check_compatibility(&self) -> Result<Compatibility, ClientError> {
+        self.block_on(self.inner.check_compatibility())?
+    }
+
+    pub fn require_compatible_server(self) -> Result<FuriosaClient, ClientError> {
+        self.block_on(self.inner.check_required_compatibility())??;
+        Ok(self)
+    }
+
+    pub fn compile(&self, request: CompileRequest) -> Result<CompiledModel, ClientError> {
+        self.block_on(self.inner.compile(request))?
+    }
+
+    pub fn compile_to_writer<W: Write>(
+        &self,
+        request: CompileRequest,
+        writer: &mut W,
+    ) -> Result<u64, ClientError> {
+        self.block_on(self.inner.compile_to_writer(request, &mut SyncWriter(writer)))?
+    }
+
+    pub fn compile_to_path<P: AsRef<Path>>(
+        &self,
+        request: CompileRequest,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.block_on(self.inner.compile_to_path(request, path))?
+    }
+
+    /// Compiles the requests and returns `(index, result)` in completion order
+    pub fn compile_many<I>(
+        &self,
+        requests: I,
+        options: BatchOptions,
+    ) -> Result<Vec<BatchResult>, ClientError>
+    where
+        I: IntoIterator<Item = CompileRequest>,
+    {
+        use futures_util::StreamExt;
+        self.block_on(self.inner.compile_many(requests, options).collect())
+    }
+
+    pub fn list_tasks(&self, filter: TaskFilter) -> Result<TaskPage, ClientError> {
+        self.block_on(self.inner.list_tasks(filter))?
+    }
+
+    pub fn list_all_tasks(&self, filter: TaskFilter) -> Result<Vec<CompileTask>, ClientError> {
+        self.block_on(self.inner.list_all_tasks(filter))?
+    }
+
+    pub fn get_task(&self, task_id: &str) -> Result<CompileTask, ClientError> {
+        self.block_on(self.inner.get_task(task_id))?
+    }
+
+    pub fn cancel_task(&self, task_id: &str) -> Result<(), ClientError> {
+        self.block_on(self.inner.cancel_task(task_id))?
+    }
+
+    pub fn task_logs(&self, task_id: &str) -> Result<String, ClientError> {
+        self.block_on(self.inner.task_logs(task_id))?
+    }
+
+    pub fn task_artifact(
+        &self,
+        task_id: &str,
+        target_ir: TargetIr,
+    ) -> Result<CompileOutput, ClientError> {
+        self.block_on(self.inner.task_artifact(task_id, target_ir))?
+    }
+
+    pub fn task_artifact_to_path<P: AsRef<Path>>(
+        &self,
+        task_id: &str,
+        target_ir: TargetIr,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.block_on(self.inner.task_artifact_to_path(task_id, target_ir, path))?
+    }
+
+    pub fn optimize(&self, request: OptimizeRequest) -> Result<Box<[u8]>, ClientError> {
+        self.block_on(self.inner.optimize(request))?
+    }
+
+    pub fn optimize_to_writer<W: Write>(
+        &self,
+        request: OptimizeRequest,
+        writer: &mut W,
+    ) -> Result<u64, ClientError> {
+        self.block_on(self.inner.optimize_to_writer(request, &mut SyncWriter(writer)))?
+    }
+
+    pub fn optimize_to_path<P: AsRef<Path>>(
+        &self,
+        request: OptimizeRequest,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.block_on(self.inner.optimize_to_path(request, path))?
     }
 
     pub fn build_calibration_model(
         &self,
         request: CalibrateRequest,
     ) -> Result<Box<[u8]>, ClientError> {
-        self.handle.block_on(self.inner.build_calibration_model(request))
+        self.block_on(self.inner.build_calibration_model(request))?
+    }
+
+    pub fn build_calibration_model_to_writer<W: Write>(
+        &self,
+        request: CalibrateRequest,
+        writer: &mut W,
+    ) -> Result<u64, ClientError> {
+        let mut writer = SyncWriter(writer);
+        self.block_on(self.inner.build_calibration_model_to_writer(request, &mut writer))?
+    }
+
+    pub fn build_calibration_model_to_path<P: AsRef<Path>>(
+        &self,
+        request: CalibrateRequest,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.block_on(self.inner.build_calibration_model_to_path(request, path))?
     }
 
     pub fn quantize(&self, request: QuantizeRequest) -> Result<Box<[u8]>, ClientError> {
-        self.handle.block_on(self.inner.quantize(request))
+        self.block_on(self.inner.quantize(request))?
+    }
+
+    pub fn quantize_to_writer<W: Write>(
+        &self,
+        request: QuantizeRequest,
+        writer: &mut W,
+    ) -> Result<u64, ClientError> {
+        self.block_on(self.inner.quantize_to_writer(request, &mut SyncWriter(writer)))?
+    }
+
+    pub fn quantize_to_path<P: AsRef<Path>>(
+        &self,
+        request: QuantizeRequest,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.block_on(self.inner.quantize_to_path(request, path))?
+    }
+}
+
+impl Drop for FuriosaClient {
+    fn drop(&mut self) {
+        // Dropping a runtime inside another runtime panics
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+/// Writes to a blocking writer, which is fine since the calling thread is blocked anyway
+struct SyncWriter<'a, W>(&'a mut W);
+
+impl<'a, W: Write> AsyncWrite for SyncWriter<'a, W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(self.get_mut().0.write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(self.get_mut().0.flush())
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(self.get_mut().0.flush())
     }
 }
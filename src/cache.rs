@@ -0,0 +1,310 @@
+//! On-disk cache of compiled artifacts
+//!
+//! Artifacts are stored under file names of the SHA-256 hash of all compile inputs, and
+//! `index.json` in the same directory keeps their sizes and the order of last accesses.
+//! Least recently used artifacts are evicted when the total size exceeds the limit.
+//! Every access holds an exclusive lock of `index.lock` and re-reads the index, so processes
+//! can share the directory.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::{ClientError, CompileRequest, ModelSource, TargetIr};
+
+static INDEX_FILE_NAME: &str = "index.json";
+static LOCK_FILE_NAME: &str = "index.lock";
+static DEFAULT_MAX_CACHE_SIZE: u64 = 1 << 30;
+
+/// How `FuriosaClient::compile` uses the compile cache
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CachePolicy {
+    /// Returns a cached artifact if exists and stores a new one otherwise
+    Use,
+    /// Always compiles and replaces the cached artifact
+    Refresh,
+    /// Neither reads nor writes the cache
+    Bypass,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CacheIndex {
+    clock: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    size: u64,
+    last_access: u64,
+}
+
+pub struct CompileCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+/// Returns true if `key` is a SHA-256 digest in lowercase hex, which can't escape the directory
+fn is_valid_key(key: &str) -> bool {
+    key.len() == 64 && key.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+impl CompileCache {
+    /// Opens the cache at `$HOME/.furiosa/cache`
+    pub fn open_default() -> Result<CompileCache, ClientError> {
+        let mut dir = dirs::home_dir().ok_or_else(|| {
+            ClientError::io_error(std::io::ErrorKind::NotFound, "home directory not found")
+        })?;
+        dir.push(".furiosa/cache");
+        CompileCache::open(dir)
+    }
+
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<CompileCache, ClientError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let cache = CompileCache { dir, max_size: DEFAULT_MAX_CACHE_SIZE };
+        // fails early if the directory can't be locked
+        cache.lock()?;
+        Ok(cache)
+    }
+
+    /// Sets the maximum total size of cached artifacts in bytes (1 GiB by default)
+    pub fn max_size(mut self, max_size: u64) -> CompileCache {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the total size of cached artifacts in bytes
+    pub fn size(&self) -> u64 {
+        match self.lock() {
+            Ok(_lock) => self.load_index().entries.values().map(|e| e.size).sum(),
+            Err(e) => {
+                warn!("fail to lock the compile cache: {}", e);
+                0
+            }
+        }
+    }
+
+    /// Returns the artifact cached under `key`, which is a SHA-256 digest in lowercase hex.
+    /// Other keys are never cached.
+    pub fn get(&self, key: &str) -> Option<Box<[u8]>> {
+        if !is_valid_key(key) {
+            warn!("invalid compile cache key: {}", key);
+            return None;
+        }
+        let _lock = self.lock().map_err(|e| warn!("fail to lock the compile cache: {}", e)).ok()?;
+        let mut index = self.load_index();
+        index.entries.get(key)?;
+        match fs::read(self.dir.join(key)) {
+            Ok(bytes) => {
+                index.clock += 1;
+                let clock = index.clock;
+                if let Some(entry) = index.entries.get_mut(key) {
+                    entry.last_access = clock;
+                }
+                self.save_index(&index);
+                Some(bytes.into_boxed_slice())
+            }
+            Err(e) => {
+                warn!("fail to read the cached artifact {}: {}", key, e);
+                index.entries.remove(key);
+                self.save_index(&index);
+                None
+            }
+        }
+    }
+
+    /// Caches `artifact` under `key`, which must be a SHA-256 digest in lowercase hex
+    pub fn put(&self, key: &str, artifact: &[u8]) -> Result<(), ClientError> {
+        if !is_valid_key(key) {
+            let msg = format!("invalid compile cache key: {}", key);
+            return Err(ClientError::io_error(std::io::ErrorKind::InvalidInput, &msg));
+        }
+        let size = artifact.len() as u64;
+        if size > self.max_size {
+            return Ok(());
+        }
+
+        let _lock = self.lock()?;
+        let mut index = self.load_index();
+        let tmp_path = self.dir.join(format!("{}.tmp", key));
+        fs::write(&tmp_path, artifact)?;
+        fs::rename(&tmp_path, self.dir.join(key))?;
+
+        index.clock += 1;
+        let last_access = index.clock;
+        index.entries.insert(key.to_string(), CacheEntry { size, last_access });
+        self.evict(&mut index);
+        self.save_index(&index);
+        Ok(())
+    }
+
+    /// Removes all cached artifacts
+    pub fn clear(&self) -> Result<(), ClientError> {
+        let _lock = self.lock()?;
+        let mut index = self.load_index();
+        for key in index.entries.keys() {
+            remove_file(&self.dir.join(key));
+        }
+        index.entries.clear();
+        self.save_index(&index);
+        Ok(())
+    }
+
+    fn evict(&self, index: &mut CacheIndex) {
+        let mut total: u64 = index.entries.values().map(|e| e.size).sum();
+        if total <= self.max_size {
+            return;
+        }
+
+        let mut entries: Vec<(String, u64, u64)> =
+            index.entries.iter().map(|(k, e)| (k.clone(), e.size, e.last_access)).collect();
+        entries.sort_by_key(|(_, _, last_access)| *last_access);
+        for (key, size, _) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            remove_file(&self.dir.join(&key));
+            index.entries.remove(&key);
+            total -= size;
+        }
+    }
+
+    /// Locks the directory until the returned file is dropped
+    fn lock(&self) -> Result<fs::File, ClientError> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.dir.join(LOCK_FILE_NAME))?;
+        file.lock_exclusive()?;
+        Ok(file)
+    }
+
+    /// Reads the index, which must be called with the lock
+    fn load_index(&self) -> CacheIndex {
+        match fs::read(self.dir.join(INDEX_FILE_NAME)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!("ignoring the broken compile cache index: {}", e);
+                CacheIndex::default()
+            }),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("fail to read the compile cache index: {}", e);
+                }
+                CacheIndex::default()
+            }
+        }
+    }
+
+    /// Replaces the index atomically, which must be called with the lock
+    fn save_index(&self, index: &CacheIndex) {
+        let bytes = serde_json::to_vec(index).expect("fail to serialize the cache index");
+        let tmp_path = self.dir.join(format!("{}.tmp", INDEX_FILE_NAME));
+        let result = fs::write(&tmp_path, bytes)
+            .and_then(|_| fs::rename(&tmp_path, self.dir.join(INDEX_FILE_NAME)));
+        if let Err(e) = result {
+            warn!("fail to write the compile cache index: {}", e);
+        }
+    }
+}
+
+fn remove_file(path: &Path) {
+    if let Err(e) = fs::remove_file(path) {
+        warn!("fail to remove the cached artifact {}: {}", path.display(), e);
+    }
+}
+
+/// Hashes the inputs of a compile request into the cache keys of its artifacts, each of which
+/// is the same as the key of a request only for the IR. The source is hashed first, so that it
+/// can be hashed while it's uploaded instead of being read twice.
+#[derive(Clone)]
+pub(crate) struct InputHasher {
+    hasher: Sha256,
+    size: u64,
+    hashed: u64,
+    target_npu_spec: String,
+    compiler_config: String,
+    target_irs: Vec<TargetIr>,
+    runtime_version: String,
+}
+
+impl InputHasher {
+    /// Returns `None` if the size of the source is unknown
+    pub(crate) fn new(request: &CompileRequest, runtime_version: &str) -> Option<InputHasher> {
+        let size = request.source.size()?;
+        let mut hasher = Sha256::new();
+        hasher.update(size.to_le_bytes());
+        Some(InputHasher {
+            hasher,
+            size,
+            hashed: 0,
+            target_npu_spec: request.target_npu_spec.to_string(),
+            compiler_config: request
+                .compiler_config
+                .as_ref()
+                .map(|c| c.to_string())
+                .unwrap_or_default(),
+            target_irs: request.requested_target_irs(),
+            runtime_version: runtime_version.to_string(),
+        })
+    }
+
+    /// Hashes the next chunk of the source
+    pub(crate) fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.hashed += chunk.len() as u64;
+    }
+
+    /// Hashes the whole source in chunks before it's uploaded. Readers are left to be hashed
+    /// while uploading because they can be read only once.
+    pub(crate) fn hash_source(&mut self, source: &ModelSource) -> std::io::Result<()> {
+        match source {
+            ModelSource::Bytes(bytes) => self.update(bytes),
+            ModelSource::File(path) => {
+                let mut file = fs::File::open(path)?;
+                let mut buf = vec![0u8; 1 << 20];
+                loop {
+                    match file.read(&mut buf)? {
+                        0 => break,
+                        n => self.update(&buf[..n]),
+                    }
+                }
+            }
+            ModelSource::Reader { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Returns the keys of `CompileRequest::requested_target_irs`, or `None` unless exactly
+    /// the size of the source has been hashed
+    pub(crate) fn keys(&self) -> Option<Vec<(TargetIr, String)>> {
+        if self.hashed != self.size {
+            return None;
+        }
+        let update = |hasher: &mut Sha256, bytes: &[u8]| {
+            hasher.update((bytes.len() as u64).to_le_bytes());
+            hasher.update(bytes);
+        };
+        let mut hasher = self.hasher.clone();
+        update(&mut hasher, self.target_npu_spec.as_bytes());
+        update(&mut hasher, self.compiler_config.as_bytes());
+        let keys = self.target_irs.iter().map(|target_ir| {
+            let mut hasher = hasher.clone();
+            update(&mut hasher, target_ir.as_str().as_bytes());
+            update(&mut hasher, self.runtime_version.as_bytes());
+            (*target_ir, format!("{:x}", hasher.finalize()))
+        });
+        Some(keys.collect())
+    }
+}
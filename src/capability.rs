@@ -0,0 +1,101 @@
+//! Features supported by the connected server
+//!
+//! Once `FuriosaClient::discover_capabilities` is called, requests which the server doesn't
+//! support fail early with `ClientError::Unsupported` before uploading models. A field which
+//! the server omits means that the server doesn't say, so nothing is rejected by it.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ClientError, CompileRequest, ModelSource, TargetIr};
+
+pub(crate) static COMPILER_API: &str = "compiler";
+pub(crate) static COMPILER_API_VERSION: &str = "v1alpha1";
+pub(crate) static DSS_API: &str = "dss";
+pub(crate) static DSS_API_VERSION: &str = "v1";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Capabilities {
+    /// Names of supported target IRs (e.g., 'enf')
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_irs: Option<Vec<String>>,
+    /// Names of NPU spec presets (e.g., '64dpes')
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub npu_presets: Option<Vec<String>>,
+    /// Supported DSS operations (e.g., 'optimize', 'build-calibration-model', 'quantize')
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dss_operations: Option<Vec<String>>,
+    /// Maximum size of uploaded models in bytes
+    #[serde(default)]
+    pub max_upload_size: Option<u64>,
+    /// Supported versions of each API (e.g., 'compiler': ['v1alpha1'])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_versions: Option<HashMap<String, Vec<String>>>,
+}
+
+impl Capabilities {
+    /// Returns false only if the server lists target IRs without `target_ir`
+    pub fn supports_target_ir(&self, target_ir: TargetIr) -> bool {
+        match &self.target_irs {
+            Some(irs) => irs.iter().any(|ir| ir.eq_ignore_ascii_case(target_ir.as_str())),
+            None => true,
+        }
+    }
+
+    /// Returns false only if the server lists DSS operations without `operation`
+    pub fn supports_dss_operation(&self, operation: &str) -> bool {
+        match &self.dss_operations {
+            Some(operations) => operations.iter().any(|op| op == operation),
+            None => true,
+        }
+    }
+
+    /// Returns false only if the server lists the versions of `api` without `version`
+    pub fn supports_api(&self, api: &str, version: &str) -> bool {
+        match self.api_versions.as_ref().and_then(|apis| apis.get(api)) {
+            Some(versions) => versions.iter().any(|v| v == version),
+            None => true,
+        }
+    }
+
+    pub(crate) fn check_compile(&self, request: &CompileRequest) -> Result<(), ClientError> {
+        self.check_api(COMPILER_API, COMPILER_API_VERSION)?;
+        for target_ir in request.requested_target_irs() {
+            if !self.supports_target_ir(target_ir) {
+                return Err(ClientError::Unsupported(format!("target IR '{}'", target_ir)));
+            }
+        }
+        self.check_upload_size(&request.source)
+    }
+
+    pub(crate) fn check_dss(
+        &self,
+        operation: &str,
+        source: &ModelSource,
+    ) -> Result<(), ClientError> {
+        self.check_api(DSS_API, DSS_API_VERSION)?;
+        if !self.supports_dss_operation(operation) {
+            return Err(ClientError::Unsupported(format!("DSS operation '{}'", operation)));
+        }
+        self.check_upload_size(source)
+    }
+
+    fn check_api(&self, api: &str, version: &str) -> Result<(), ClientError> {
+        if self.supports_api(api, version) {
+            Ok(())
+        } else {
+            Err(ClientError::Unsupported(format!("{} API {}", api, version)))
+        }
+    }
+
+    fn check_upload_size(&self, source: &ModelSource) -> Result<(), ClientError> {
+        match (self.max_upload_size, source.size()) {
+            (Some(max), Some(size)) if size > max => Err(ClientError::Unsupported(format!(
+                "model of {} bytes (the maximum upload size is {} bytes)",
+                size, max
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Recording and replaying HTTP exchanges with Furiosa API
+//!
+//! In the record mode, every request sent by `FuriosaClient` and its response are written to
+//! a cassette directory: `cassette.json` keeps the requests without API keys and the response
+//! statuses and headers, and each response body (e.g., an artifact) is kept in its own file.
+//! In the replay mode, the recorded responses are returned in order without network, so
+//! integrations can be tested without API keys.
+//!
+//! Requests are matched by the method and the path including the query. If a request is sent
+//! more times than recorded (e.g., polling a compile task), the last matching response is
+//! returned again.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Request, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ClientError, Transport, TransportFuture, ACCESS_KEY_ID_HTTP_HEADER,
+    SECRET_ACCESS_KEY_HTTP_HEADER,
+};
+
+pub static FURIOSA_CASSETTE_ENV: &str = "FURIOSA_CASSETTE";
+pub static FURIOSA_CASSETTE_MODE_ENV: &str = "FURIOSA_CASSETTE_MODE";
+static CASSETTE_FILE_NAME: &str = "cassette.json";
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CassetteMode {
+    /// Sends requests to the server and records the exchanges
+    Record,
+    /// Returns the recorded responses without network
+    Replay,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CassetteIndex {
+    interactions: Vec<Interaction>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct Interaction {
+    method: String,
+    path: String,
+    request_headers: Vec<(String, String)>,
+    /// File name of the request body. Streamed bodies (e.g., multipart forms) aren't recorded.
+    request_body: Option<String>,
+    status: u16,
+    response_headers: Vec<(String, String)>,
+    /// File name of the response body
+    response_body: String,
+}
+
+struct ReplayState {
+    index: CassetteIndex,
+    used: Vec<bool>,
+}
+
+pub struct Cassette {
+    dir: PathBuf,
+    mode: CassetteMode,
+    state: Mutex<ReplayState>,
+}
+
+impl Cassette {
+    /// Starts recording into `dir`. Exchanges recorded in `dir` before are discarded.
+    pub fn record<P: AsRef<Path>>(dir: P) -> Result<Cassette, ClientError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let cassette = Cassette::new(dir, CassetteMode::Record, CassetteIndex::default());
+        cassette.save(&cassette.state.lock().unwrap().index)?;
+        Ok(cassette)
+    }
+
+    /// Loads the exchanges recorded in `dir` to replay them
+    pub fn replay<P: AsRef<Path>>(dir: P) -> Result<Cassette, ClientError> {
+        let dir = dir.as_ref().to_path_buf();
+        let index = serde_json::from_slice(&fs::read(dir.join(CASSETTE_FILE_NAME))?)
+            .map_err(|e| ClientError::Cassette(format!("invalid {}: {}", CASSETTE_FILE_NAME, e)))?;
+        Ok(Cassette::new(dir, CassetteMode::Replay, index))
+    }
+
+    /// Opens the cassette of `FURIOSA_CASSETTE` directory if it's set. `FURIOSA_CASSETTE_MODE`
+    /// selects 'record' or 'replay', which is the default.
+    pub fn from_env() -> Result<Option<Cassette>, ClientError> {
+        let dir = match std::env::var(FURIOSA_CASSETTE_ENV) {
+            Ok(dir) if !dir.is_empty() => dir,
+            _ => return Ok(None),
+        };
+        match std::env::var(FURIOSA_CASSETTE_MODE_ENV).as_deref() {
+            Ok("record") => Cassette::record(dir).map(Some),
+            Ok("replay") | Ok("") | Err(_) => Cassette::replay(dir).map(Some),
+            Ok(mode) => Err(ClientError::Cassette(format!("unknown cassette mode '{}'", mode))),
+        }
+    }
+
+    fn new(dir: PathBuf, mode: CassetteMode, index: CassetteIndex) -> Cassette {
+        let used = vec![false; index.interactions.len()];
+        Cassette { dir, mode, state: Mutex::new(ReplayState { index, used }) }
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    async fn record_exchange(
+        &self,
+        inner: &dyn Transport,
+        request: Request,
+    ) -> Result<Response, ClientError> {
+        let method = request.method().to_string();
+        let path = request_path(&request);
+        let request_headers: Vec<(String, String)> = request
+            .headers()
+            .iter()
+            .filter(|(name, _)| !is_secret(name))
+            .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or_default().to_string()))
+            .collect();
+        let request_body = request.body().and_then(|b| b.as_bytes()).map(|b| b.to_vec());
+
+        let response = inner.send(request).await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.bytes().await.map_err(|e| ClientError::Transport(e.to_string()))?;
+
+        {
+            let mut state = self.state.lock().unwrap();
+            let seq = state.index.interactions.len();
+            let request_body = match request_body {
+                Some(bytes) => {
+                    let name = format!("{:04}.request", seq);
+                    fs::write(self.dir.join(&name), bytes)?;
+                    Some(name)
+                }
+                None => None,
+            };
+            let response_body = format!("{:04}.response", seq);
+            fs::write(self.dir.join(&response_body), &body)?;
+            state.index.interactions.push(Interaction {
+                method,
+                path,
+                request_headers,
+                request_body,
+                status: status.as_u16(),
+                response_headers: headers
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or_default().to_string()))
+                    .collect(),
+                response_body,
+            });
+            state.used.push(true);
+            self.save(&state.index)?;
+        }
+
+        make_response(status.as_u16(), headers, body.to_vec())
+    }
+
+    fn replay_exchange(&self, request: &Request) -> Result<Response, ClientError> {
+        let method = request.method().to_string();
+        let path = request_path(request);
+        let mut state = self.state.lock().unwrap();
+        let matches = |i: &Interaction| i.method == method && i.path == path;
+        let position = state
+            .index
+            .interactions
+            .iter()
+            .enumerate()
+            .position(|(seq, i)| !state.used[seq] && matches(i))
+            .or_else(|| state.index.interactions.iter().rposition(matches));
+        let seq = position.ok_or_else(|| {
+            ClientError::Cassette(format!("no recorded response for {} {}", method, path))
+        })?;
+        state.used[seq] = true;
+
+        let interaction = &state.index.interactions[seq];
+        let mut headers = HeaderMap::new();
+        for (name, value) in &interaction.response_headers {
+            if let (Ok(name), Ok(value)) =
+                (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value))
+            {
+                headers.append(name, value);
+            }
+        }
+        let body = fs::read(self.dir.join(&interaction.response_body))?;
+        make_response(interaction.status, headers, body)
+    }
+
+    fn save(&self, index: &CassetteIndex) -> Result<(), ClientError> {
+        let json = serde_json::to_vec_pretty(index).expect("fail to serialize the cassette");
+        fs::write(self.dir.join(CASSETTE_FILE_NAME), json)?;
+        Ok(())
+    }
+}
+
+/// Sends requests with the inner transport and records the exchanges, or replays them
+pub(crate) struct CassetteTransport {
+    cassette: Cassette,
+    inner: Arc<dyn Transport>,
+}
+
+impl CassetteTransport {
+    pub(crate) fn new(cassette: Cassette, inner: Arc<dyn Transport>) -> CassetteTransport {
+        CassetteTransport { cassette, inner }
+    }
+}
+
+impl Transport for CassetteTransport {
+    fn send(&self, request: Request) -> TransportFuture<'_> {
+        Box::pin(async move {
+            match self.cassette.mode {
+                CassetteMode::Record => {
+                    self.cassette.record_exchange(self.inner.as_ref(), request).await
+                }
+                CassetteMode::Replay => self.cassette.replay_exchange(&request),
+            }
+        })
+    }
+}
+
+fn request_path(request: &Request) -> String {
+    let url = request.url();
+    match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    }
+}
+
+fn is_secret(name: &HeaderName) -> bool {
+    name.as_str().eq_ignore_ascii_case(ACCESS_KEY_ID_HTTP_HEADER)
+        || name.as_str().eq_ignore_ascii_case(SECRET_ACCESS_KEY_HTTP_HEADER)
+}
+
+fn make_response(status: u16, headers: HeaderMap, body: Vec<u8>) -> Result<Response, ClientError> {
+    let mut response = http::Response::builder()
+        .status(status)
+        .body(body)
+        .map_err(|e| ClientError::Cassette(format!("invalid recorded response: {}", e)))?;
+    *response.headers_mut() = headers;
+    Ok(Response::from(response))
+}
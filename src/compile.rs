@@ -1,11 +1,15 @@
-use std::borrow::Cow;
-
-use crate::ClientError;
-use serde::Deserialize;
+use crate::{CachePolicy, ClientError, Labels, ModelSource, OperatorReport};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
 use std::str::FromStr;
+use tokio::io::AsyncRead;
 
-#[derive(Copy, Clone)]
+/// IRs in the order of lowering
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[serde(rename_all = "lowercase")]
 pub enum TargetIr {
     Dfg,
     Ldfg,
@@ -15,7 +19,15 @@ pub enum TargetIr {
     Enf,
 }
 
+static TARGET_IRS: [TargetIr; 6] =
+    [TargetIr::Dfg, TargetIr::Ldfg, TargetIr::Cdfg, TargetIr::Gir, TargetIr::Lir, TargetIr::Enf];
+
 impl TargetIr {
+    /// Returns all IRs in the order of lowering
+    pub fn iter() -> impl Iterator<Item = TargetIr> {
+        TARGET_IRS.iter().copied()
+    }
+
     pub fn as_str(&self) -> &str {
         use TargetIr::*;
         match self {
@@ -27,6 +39,41 @@ impl TargetIr {
             Enf => "enf",
         }
     }
+
+    /// Returns the name of the artifact in `tasks/{id}/artifacts/`, `output.<ir>`.
+    ///
+    /// The compiler API names the artifact of a task `output.enf`, which is the only name used
+    /// before IRs other than ENF could be requested. The other IRs are assumed to follow it with
+    /// their extensions (e.g., `output.gir`), since the API neither documents their names nor
+    /// lists the artifacts of a task. Downloads fail with `ClientError::Unsupported` if the
+    /// server has no artifact by the assumed name.
+    pub fn artifact_name(&self) -> String {
+        format!("output.{}", self.as_str())
+    }
+
+    /// ENF is a binary, and the other IRs are assumed to be textual dumps, which the API doesn't
+    /// specify either. It only decides how `CompileOutput::decode` decodes an artifact, which
+    /// keeps a dump that isn't valid UTF-8 as bytes.
+    pub fn is_textual(&self) -> bool {
+        !matches!(self, TargetIr::Enf)
+    }
+
+    /// Returns the media type sent in `Accept` when downloading the artifact, `text/plain` for
+    /// textual IRs. The server isn't known to negotiate it, and the downloaded bytes are kept
+    /// as they are regardless of the `Content-Type` of the response.
+    pub fn content_type(&self) -> &str {
+        if self.is_textual() {
+            "text/plain"
+        } else {
+            "application/octet-stream"
+        }
+    }
+}
+
+impl fmt::Display for TargetIr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl FromStr for TargetIr {
@@ -51,29 +98,72 @@ pub struct CompileRequest {
     pub target_npu_spec: Value,
     pub compiler_config: Option<Value>,
     pub target_ir: TargetIr,
+    /// IRs whose artifacts are returned with the one of `target_ir` from the same task
+    pub extra_target_irs: BTreeSet<TargetIr>,
     pub filename: String,
-    pub source: Vec<u8>,
+    pub source: ModelSource,
+    pub cache_policy: CachePolicy,
+    pub labels: Labels,
 }
 
 impl CompileRequest {
-    pub fn new<'a, S: Into<Cow<'a, [u8]>>>(target_npu_spec: Value, source: S) -> CompileRequest {
+    pub fn new<S: Into<ModelSource>>(target_npu_spec: Value, source: S) -> CompileRequest {
         CompileRequest {
             target_npu_spec,
             compiler_config: None,
             target_ir: TargetIr::Enf,
+            extra_target_irs: BTreeSet::new(),
             filename: String::from("noname"),
-            source: match source.into() {
-                Cow::Borrowed(value) => Vec::from(value),
-                Cow::Owned(value) => value,
-            },
+            source: source.into(),
+            cache_policy: CachePolicy::Use,
+            labels: Labels::new(),
         }
     }
 
+    /// Creates a request which streams the model file named after the file
+    pub fn from_path<P: AsRef<Path>>(target_npu_spec: Value, path: P) -> CompileRequest {
+        let filename = crate::source::file_name(&path);
+        CompileRequest::new(target_npu_spec, ModelSource::from_path(path)).filename(&filename)
+    }
+
+    /// Creates a request which streams the model from `reader` of `length` bytes
+    pub fn from_reader<R>(target_npu_spec: Value, reader: R, length: u64) -> CompileRequest
+    where
+        R: AsyncRead + Send + Sync + Unpin + 'static,
+    {
+        CompileRequest::new(target_npu_spec, ModelSource::from_reader(reader, length))
+    }
+
     pub fn target_ir(mut self, target_format: TargetIr) -> CompileRequest {
         self.target_ir = target_format;
         self
     }
 
+    /// Also returns the artifact of `target_ir` from the same task
+    pub fn extra_target_ir(mut self, target_ir: TargetIr) -> CompileRequest {
+        self.extra_target_irs.insert(target_ir);
+        self
+    }
+
+    /// Returns the artifact of `target_ir` with the ones of `extra_target_irs` from one task,
+    /// replacing the IRs requested before
+    pub fn target_irs<I: IntoIterator<Item = TargetIr>>(
+        mut self,
+        target_ir: TargetIr,
+        extra_target_irs: I,
+    ) -> CompileRequest {
+        self.target_ir = target_ir;
+        self.extra_target_irs =
+            extra_target_irs.into_iter().filter(|&ir| ir != target_ir).collect();
+        self
+    }
+
+    /// Returns `target_ir` followed by the other IRs in `extra_target_irs`
+    pub fn requested_target_irs(&self) -> Vec<TargetIr> {
+        let extra = self.extra_target_irs.iter().filter(|&&ir| ir != self.target_ir);
+        std::iter::once(self.target_ir).chain(extra.copied()).collect()
+    }
+
     pub fn compile_config(mut self, compile_config: Value) -> CompileRequest {
         self.compiler_config = Some(compile_config);
         self
@@ -83,9 +173,40 @@ impl CompileRequest {
         self.filename = String::from(filename);
         self
     }
+
+    /// Sets how the request uses the compile cache if it is enabled in the client
+    pub fn cache_policy(mut self, cache_policy: CachePolicy) -> CompileRequest {
+        self.cache_policy = cache_policy;
+        self
+    }
+
+    /// Adds a label echoed back on `CompileTask`, which is validated when the request is sent
+    pub fn label(mut self, key: &str, value: &str) -> CompileRequest {
+        self.labels.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Returns a copy of the request unless the source is a reader
+    pub fn try_clone(&self) -> Option<CompileRequest> {
+        Some(CompileRequest {
+            target_npu_spec: self.target_npu_spec.clone(),
+            compiler_config: self.compiler_config.clone(),
+            target_ir: self.target_ir,
+            extra_target_irs: self.extra_target_irs.clone(),
+            filename: self.filename.clone(),
+            source: self.source.try_clone()?,
+            cache_policy: self.cache_policy,
+            labels: self.labels.clone(),
+        })
+    }
+
+    /// Checks the operators of the source model against the target NPU without calling the API
+    pub fn check_operators(&self) -> Result<OperatorReport, ClientError> {
+        crate::check_operators(&self.target_npu_spec, &self.source.to_bytes()?)
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CompileTask {
     pub version: i32,
     pub task_id: String,
@@ -95,9 +216,11 @@ pub struct CompileTask {
     pub finish_time: Option<i64>,
     pub progress: f32,
     pub error_message: Option<String>,
+    #[serde(default, skip_serializing_if = "Labels::is_empty")]
+    pub labels: Labels,
 }
 
-#[derive(Deserialize, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
 pub enum CompileTaskPhase {
     Pending,
     Running,
@@ -110,3 +233,114 @@ impl CompileTaskPhase {
         self == &CompileTaskPhase::Succeeded || self == &CompileTaskPhase::Failed
     }
 }
+
+impl FromStr for CompileTaskPhase {
+    type Err = ClientError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use CompileTaskPhase::*;
+        let phase = match s.to_lowercase().as_str() {
+            "pending" => Pending,
+            "running" => Running,
+            "succeeded" => Succeeded,
+            "failed" => Failed,
+            _ => {
+                let msg = format!("unknown task phase '{}'", s);
+                return Err(ClientError::io_error(std::io::ErrorKind::InvalidInput, &msg));
+            }
+        };
+        Ok(phase)
+    }
+}
+
+/// Conditions of compile tasks to be listed by `FuriosaClient::list_tasks`
+#[derive(Clone, Debug, Default)]
+pub struct TaskFilter {
+    pub phases: Vec<CompileTaskPhase>,
+    /// Unix timestamps in seconds
+    pub submitted_after: Option<i64>,
+    pub submitted_before: Option<i64>,
+    pub labels: Vec<(String, String)>,
+    pub page_size: Option<u32>,
+    /// `TaskPage::next_page_token` of the previous page
+    pub page_token: Option<String>,
+}
+
+impl TaskFilter {
+    /// Matches all tasks in the first page
+    pub fn new() -> TaskFilter {
+        TaskFilter::default()
+    }
+
+    /// Matches tasks in any of the added phases
+    pub fn phase(mut self, phase: CompileTaskPhase) -> TaskFilter {
+        self.phases.push(phase);
+        self
+    }
+
+    pub fn submitted_after(mut self, timestamp: i64) -> TaskFilter {
+        self.submitted_after = Some(timestamp);
+        self
+    }
+
+    pub fn submitted_before(mut self, timestamp: i64) -> TaskFilter {
+        self.submitted_before = Some(timestamp);
+        self
+    }
+
+    /// Matches tasks which have all of the added labels
+    pub fn label(mut self, key: &str, value: &str) -> TaskFilter {
+        self.labels.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn page_size(mut self, page_size: u32) -> TaskFilter {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    pub fn page_token(mut self, page_token: &str) -> TaskFilter {
+        self.page_token = Some(page_token.to_string());
+        self
+    }
+
+    pub(crate) fn to_query(&self) -> Vec<(&'static str, String)> {
+        let mut query = Vec::new();
+        for phase in &self.phases {
+            query.push(("phase", format!("{:?}", phase)));
+        }
+        if let Some(after) = self.submitted_after {
+            query.push(("submitted_after", after.to_string()));
+        }
+        if let Some(before) = self.submitted_before {
+            query.push(("submitted_before", before.to_string()));
+        }
+        for (key, value) in &self.labels {
+            query.push(("label", format!("{}={}", key, value)));
+        }
+        if let Some(page_size) = self.page_size {
+            query.push(("page_size", page_size.to_string()));
+        }
+        if let Some(page_token) = &self.page_token {
+            query.push(("page_token", page_token.clone()));
+        }
+        query
+    }
+
+    /// Checks the phase, the submit time and the labels, for servers which ignore the filter
+    pub(crate) fn matches(&self, task: &CompileTask) -> bool {
+        (self.phases.is_empty() || self.phases.contains(&task.phase))
+            && !matches!(self.submitted_after, Some(after) if task.submit_time < after)
+            && !matches!(self.submitted_before, Some(before) if task.submit_time >= before)
+            && self.labels.iter().all(|(k, v)| task.labels.get(k) == Some(v))
+    }
+}
+
+/// A page of compile tasks
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TaskPage {
+    pub tasks: Vec<CompileTask>,
+    /// Token to get the next page, which is `None` for the last page
+    #[serde(default)]
+    pub next_page_token: Option<String>,
+}
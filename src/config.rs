@@ -0,0 +1,212 @@
+//! Profiles of API keys and endpoints stored in `$HOME/.furiosa`
+//!
+//! The default profile is kept in `$HOME/.furiosa/credential` and `$HOME/.furiosa/config`.
+//! Other profiles are kept in `credential.<profile>` and `config.<profile>`, and selected by
+//! `FURIOSA_PROFILE` environment variable.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    get_endpoint_from_env, load_config_file, ClientError, FuriosaClient, TaskFilter,
+    ACCESS_KEY_ID_ENV, FURIOSA_API_ENDPOINT_ENV, SECRET_ACCESS_KEY_ENV,
+};
+
+pub static FURIOSA_PROFILE_ENV: &str = "FURIOSA_PROFILE";
+pub static DEFAULT_PROFILE: &str = "default";
+static CREDENTIAL_FILE_NAME: &str = "credential";
+static CONFIG_FILE_NAME: &str = "config";
+
+/// Returns the profile selected by `FURIOSA_PROFILE`, or the default profile
+pub fn current_profile() -> String {
+    std::env::var(FURIOSA_PROFILE_ENV)
+        .ok()
+        .filter(|p| !p.is_empty())
+        .unwrap_or_else(|| String::from(DEFAULT_PROFILE))
+}
+
+pub(crate) fn profile_file_name(file: &str, profile: &str) -> String {
+    if profile == DEFAULT_PROFILE {
+        file.to_string()
+    } else {
+        format!("{}.{}", file, profile)
+    }
+}
+
+fn furiosa_dir() -> Result<PathBuf, ClientError> {
+    let mut dir = dirs::home_dir().ok_or_else(|| {
+        ClientError::io_error(io::ErrorKind::NotFound, "home directory not found")
+    })?;
+    dir.push(".furiosa");
+    Ok(dir)
+}
+
+/// API keys and endpoint of a profile to be written by `furiosa configure`
+pub struct Profile {
+    pub name: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// The default endpoint is used if it's `None`
+    pub endpoint: Option<String>,
+}
+
+impl Profile {
+    /// Checks if the server accepts the API keys by calling an authenticated API
+    pub async fn verify<S: AsRef<str>>(&self, runtime_version: S) -> Result<(), ClientError> {
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => get_endpoint_from_env()?,
+        };
+        let client = FuriosaClient::with_credential(
+            runtime_version,
+            endpoint,
+            &self.access_key_id,
+            &self.secret_access_key,
+        )?;
+        client.list_tasks(TaskFilter::new().page_size(1)).await.map(|_| ())
+    }
+
+    /// Writes the credential and config files of the profile readable only by the owner.
+    /// Other entries in existing files are kept. Returns the paths of written files.
+    pub fn save(&self) -> Result<Vec<PathBuf>, ClientError> {
+        let dir = furiosa_dir()?;
+        fs::create_dir_all(&dir)?;
+        #[cfg(unix)]
+        set_mode(&dir, 0o700)?;
+
+        let credential = dir.join(profile_file_name(CREDENTIAL_FILE_NAME, &self.name));
+        update_env_file(
+            &credential,
+            &[
+                (ACCESS_KEY_ID_ENV, Some(&self.access_key_id)),
+                (SECRET_ACCESS_KEY_ENV, Some(&self.secret_access_key)),
+            ],
+        )?;
+        let config = dir.join(profile_file_name(CONFIG_FILE_NAME, &self.name));
+        update_env_file(&config, &[(FURIOSA_API_ENDPOINT_ENV, self.endpoint.as_ref())])?;
+        Ok(vec![credential, config])
+    }
+}
+
+/// Replaces or appends `KEY=VALUE` lines, and removes the keys of which values are `None`
+fn update_env_file(path: &Path, entries: &[(&str, Option<&String>)]) -> Result<(), ClientError> {
+    let existing = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut lines: Vec<String> = existing
+        .lines()
+        .filter(|line| {
+            let key = line.split('=').next().unwrap_or_default().trim();
+            !entries.iter().any(|(k, _)| *k == key)
+        })
+        .map(String::from)
+        .collect();
+    for (key, value) in entries {
+        if let Some(value) = value {
+            lines.push(format!("{}={}", key, value));
+        }
+    }
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    write_private_file(path, content.as_bytes())
+}
+
+fn write_private_file(path: &Path, content: &[u8]) -> Result<(), ClientError> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    io::Write::write_all(&mut options.open(path)?, content)?;
+    // `mode()` only applies to new files
+    #[cfg(unix)]
+    set_mode(path, 0o600)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> Result<(), ClientError> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+/// The configuration which `FuriosaClient::new` will use
+#[derive(Serialize, Debug, Clone)]
+pub struct ResolvedConfig {
+    pub profile: String,
+    pub endpoint: String,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    /// Files of the profile which exist
+    pub files: Vec<PathBuf>,
+}
+
+impl ResolvedConfig {
+    /// Loads the files of the current profile and resolves the configuration in the same way
+    /// as `FuriosaClient::new`. Environment variables take precedence over the files.
+    pub fn resolve() -> Result<ResolvedConfig, ClientError> {
+        let profile = current_profile();
+        let dir = furiosa_dir()?;
+        let mut files = Vec::new();
+        for file in &[CONFIG_FILE_NAME, CREDENTIAL_FILE_NAME] {
+            let name = profile_file_name(file, &profile);
+            load_config_file(&name)?;
+            if dir.join(&name).exists() {
+                files.push(dir.join(&name));
+            }
+        }
+
+        Ok(ResolvedConfig {
+            profile,
+            endpoint: get_endpoint_from_env()?,
+            access_key_id: std::env::var(ACCESS_KEY_ID_ENV).ok(),
+            secret_access_key: std::env::var(SECRET_ACCESS_KEY_ENV).ok(),
+            files,
+        })
+    }
+
+    /// Returns a copy of which API keys are masked except the first 4 characters
+    pub fn masked(&self) -> ResolvedConfig {
+        ResolvedConfig {
+            access_key_id: self.access_key_id.as_deref().map(mask),
+            secret_access_key: self.secret_access_key.as_deref().map(mask),
+            ..self.clone()
+        }
+    }
+}
+
+/// Displays the configuration with masked API keys
+impl fmt::Display for ResolvedConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let masked = self.masked();
+        let unset = String::from("(not set)");
+        writeln!(f, "profile: {}", masked.profile)?;
+        writeln!(f, "endpoint: {}", masked.endpoint)?;
+        writeln!(f, "access_key_id: {}", masked.access_key_id.as_ref().unwrap_or(&unset))?;
+        write!(f, "secret_access_key: {}", masked.secret_access_key.as_ref().unwrap_or(&unset))?;
+        for file in &masked.files {
+            write!(f, "\nfile: {}", file.display())?;
+        }
+        Ok(())
+    }
+}
+
+fn mask(secret: &str) -> String {
+    let visible: String = secret.chars().take(4).collect();
+    if secret.chars().count() <= 8 {
+        "*".repeat(secret.chars().count())
+    } else {
+        format!("{}{}", visible, "*".repeat(secret.chars().count() - 4))
+    }
+}
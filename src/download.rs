@@ -0,0 +1,355 @@
+//! Streaming downloads of artifacts into writers and files
+//!
+//! Artifacts are written chunk by chunk as they arrive, so they don't need to fit in memory.
+//! The received length and the SHA-256 checksum are verified against `Content-Length` and
+//! `X-FuriosaAI-Checksum-SHA256` headers if the server sends them. Interrupted downloads of
+//! compile artifacts are resumed with `Range` requests, also after server and transport errors.
+//! A temporary file is kept only when the download was interrupted, and removed on other failures.
+
+use std::path::{Path, PathBuf};
+
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Response, StatusCode};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::warn;
+
+use crate::{
+    check_artifact_status, make_error_response, ClientError, FuriosaClient, Metric, TargetIr,
+};
+
+pub(crate) static CHECKSUM_HTTP_HEADER: &str = "X-FuriosaAI-Checksum-SHA256";
+const MAX_DOWNLOAD_ATTEMPTS: usize = 5;
+/// Number of hex digits of the URL digest in the name of a temporary file
+const PARTIAL_DIGEST_LEN: usize = 12;
+
+struct Download {
+    /// Number of bytes written so far
+    written: u64,
+    /// Expected total length from `Content-Length`
+    total: Option<u64>,
+    checksum: Option<String>,
+    hasher: Sha256,
+}
+
+impl Download {
+    fn new(written: u64, hasher: Sha256) -> Download {
+        Download { written, total: None, checksum: None, hasher }
+    }
+
+    /// Writes the response body to `writer`, skipping the first `skip` bytes
+    async fn copy<W>(
+        &mut self,
+        mut response: Response,
+        writer: &mut W,
+        mut skip: u64,
+    ) -> Result<(), ReadError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        if let Some(len) = response.content_length() {
+            self.total = Some(len + self.written - skip);
+        }
+        if let Some(checksum) = response.headers().get(CHECKSUM_HTTP_HEADER) {
+            self.checksum = checksum.to_str().ok().map(|s| s.to_lowercase());
+        }
+
+        while let Some(mut chunk) = response.chunk().await.map_err(ReadError::Body)? {
+            if skip > 0 {
+                let n = skip.min(chunk.len() as u64);
+                skip -= n;
+                let _ = chunk.split_to(n as usize);
+            }
+            writer.write_all(&chunk).await.map_err(ReadError::Write)?;
+            self.hasher.update(&chunk);
+            self.written += chunk.len() as u64;
+        }
+        writer.flush().await.map_err(ReadError::Write)?;
+
+        match self.total {
+            Some(total) if total != self.written => Err(ReadError::Incomplete(total)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Completes a resumed download whose range the server couldn't satisfy, which means
+    /// everything has been written if `Content-Range: bytes */<length>` matches `written`
+    fn complete_unsatisfied(&mut self, response: &Response) -> bool {
+        let length = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.strip_prefix("bytes */"))
+            .and_then(|length| length.trim().parse::<u64>().ok());
+        if length != Some(self.written) {
+            return false;
+        }
+        self.total = length;
+        if let Some(checksum) = response.headers().get(CHECKSUM_HTTP_HEADER) {
+            self.checksum = checksum.to_str().ok().map(|s| s.to_lowercase());
+        }
+        true
+    }
+
+    /// Returns whether a partial response starts at `written`, given `Content-Range: bytes
+    /// <start>-<end>/<length>`
+    fn resumes_at_written(&self, response: &Response) -> bool {
+        let start = response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|range| range.to_str().ok())
+            .and_then(|range| range.strip_prefix("bytes "))
+            .and_then(|range| range.split('-').next())
+            .and_then(|start| start.trim().parse::<u64>().ok());
+        start == Some(self.written)
+    }
+
+    /// Writes a response to a request of the artifact at `url` into `writer`. Returns `Some`
+    /// with the reason if the download should be resumed, and `None` if it's completed.
+    async fn copy_response<W>(
+        &mut self,
+        url: &str,
+        response: Response,
+        writer: &mut W,
+    ) -> Result<Option<String>, ClientError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let status = response.status();
+        // a file completed before the interruption has nothing left in the range
+        if status == StatusCode::RANGE_NOT_SATISFIABLE && self.written > 0 {
+            if !self.complete_unsatisfied(&response) {
+                let msg = format!("{}: {} bytes exceed the artifact", url, self.written);
+                return Err(ClientError::ApiError(msg));
+            }
+            return Ok(None);
+        }
+        if !status.is_success() {
+            return match make_error_response(url, response).await {
+                e @ ClientError::ServerError(..) => Ok(Some(e.to_string())),
+                e => Err(e),
+            };
+        }
+        // The server may ignore the range and send the whole artifact again
+        let skip = match status {
+            StatusCode::PARTIAL_CONTENT if self.resumes_at_written(&response) => 0,
+            StatusCode::PARTIAL_CONTENT => {
+                let msg =
+                    format!("{}: the partial content doesn't start at byte {}", url, self.written);
+                return Err(ClientError::ApiError(msg));
+            }
+            _ => self.written,
+        };
+
+        match self.copy(response, writer, skip).await {
+            Ok(()) => Ok(None),
+            Err(ReadError::Write(e)) => Err(e.into()),
+            Err(ReadError::Body(e)) => Ok(Some(e.to_string())),
+            Err(ReadError::Incomplete(total)) => {
+                Ok(Some(format!("received {} of {} bytes", self.written, total)))
+            }
+        }
+    }
+
+    fn verify(self) -> Result<u64, ClientError> {
+        if let Some(expected) = self.checksum {
+            let actual = format!("{:x}", self.hasher.finalize());
+            if actual != expected {
+                return Err(ClientError::ChecksumMismatch(expected, actual));
+            }
+        }
+        Ok(self.written)
+    }
+}
+
+enum ReadError {
+    Body(reqwest::Error),
+    Write(std::io::Error),
+    Incomplete(u64),
+}
+
+impl FuriosaClient {
+    /// Downloads the artifact of `target_ir` into `writer` and returns the number of written
+    /// bytes. `written` bytes of the artifact must have been already written into `writer` and
+    /// `hasher` must have been updated with them. `operation` names the downloaded bytes metric.
+    pub(crate) async fn download<W>(
+        &self,
+        operation: &'static str,
+        target_ir: TargetIr,
+        url: &str,
+        writer: &mut W,
+        written: u64,
+        hasher: Sha256,
+    ) -> Result<u64, ClientError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        let mut download = Download::new(written, hasher);
+        let mut attempt = 1;
+        loop {
+            let mut request = self.set_default_headers(self.client.get(url));
+            if download.written > 0 {
+                request = request.header(RANGE, format!("bytes={}-", download.written));
+            }
+            let response = self.send(request).await;
+            if let Ok(response) = &response {
+                check_artifact_status(target_ir, response.status())?;
+            }
+            let error = match response {
+                Ok(response) => match download.copy_response(url, response, writer).await? {
+                    Some(error) => error,
+                    None => {
+                        let bytes = download.verify()?;
+                        self.record_metric(Metric::BytesDownloaded { operation, bytes });
+                        return Ok(bytes);
+                    }
+                },
+                // keeps the written bytes to resume from them
+                Err(e @ ClientError::ServerError(..)) | Err(e @ ClientError::Transport(_)) => {
+                    e.to_string()
+                }
+                Err(e) => return Err(e),
+            };
+            if attempt >= MAX_DOWNLOAD_ATTEMPTS {
+                return Err(ClientError::Download(format!("{}: {}", url, error)));
+            }
+            warn!(url, attempt, error = error.as_str(), "resuming the interrupted download");
+            self.record_metric(Metric::Retry { operation: "download" });
+            attempt += 1;
+        }
+    }
+
+    /// Downloads an artifact into a file. The artifact is written to a temporary file next to
+    /// `path` first, and renamed to `path` once completed. A temporary file left by an
+    /// interrupted download of the same URL is resumed, and removed on other failures. The ones
+    /// left for `path` by other URLs, e.g., by an earlier compile task, are removed since they
+    /// can't be resumed.
+    pub(crate) async fn download_to_path(
+        &self,
+        operation: &'static str,
+        target_ir: TargetIr,
+        url: &str,
+        path: &Path,
+    ) -> Result<u64, ClientError> {
+        let partial = partial_path(path, url);
+        remove_stale_partials(path, &partial).await;
+        let mut hasher = Sha256::new();
+        let mut written = 0;
+        if let Ok(mut file) = tokio::fs::File::open(&partial).await {
+            let mut buf = vec![0u8; 1 << 20];
+            loop {
+                match file.read(&mut buf).await? {
+                    0 => break,
+                    n => {
+                        hasher.update(&buf[..n]);
+                        written += n as u64;
+                    }
+                }
+            }
+        }
+
+        let mut file =
+            tokio::fs::OpenOptions::new().create(true).append(true).open(&partial).await?;
+        let written =
+            match self.download(operation, target_ir, url, &mut file, written, hasher).await {
+                Ok(written) => written,
+                Err(e) => {
+                    // only an interrupted download can be resumed by the next call
+                    if !matches!(e, ClientError::Download(_)) {
+                        drop(file);
+                        let _ = tokio::fs::remove_file(&partial).await;
+                    }
+                    return Err(e);
+                }
+            };
+        file.sync_all().await?;
+        drop(file);
+        tokio::fs::rename(&partial, path).await?;
+        Ok(written)
+    }
+}
+
+/// Writes the body of a successful response into `writer` and returns the number of bytes
+pub(crate) async fn write_response<W>(
+    response: Response,
+    writer: &mut W,
+) -> Result<u64, ClientError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut download = Download::new(0, Sha256::new());
+    match download.copy(response, writer, 0).await {
+        Ok(()) => download.verify(),
+        Err(ReadError::Write(e)) => Err(e.into()),
+        Err(ReadError::Body(e)) => Err(ClientError::Download(e.to_string())),
+        Err(ReadError::Incomplete(total)) => {
+            let msg = format!("received {} of {} bytes", download.written, total);
+            Err(ClientError::Download(msg))
+        }
+    }
+}
+
+/// Writes the body of a successful response into a temporary file next to `path`, and
+/// renames it to `path` once completed
+pub(crate) async fn write_response_to_path(
+    response: Response,
+    path: &Path,
+) -> Result<u64, ClientError> {
+    let partial = partial_path(path, response.url().as_str());
+    let mut file = tokio::fs::File::create(&partial).await?;
+    let written = match write_response(response, &mut file).await {
+        Ok(written) => written,
+        Err(e) => {
+            drop(file);
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(e);
+        }
+    };
+    file.sync_all().await?;
+    drop(file);
+    tokio::fs::rename(&partial, path).await?;
+    Ok(written)
+}
+
+/// Returns the temporary file path of a download, which is distinct for each URL
+fn partial_path(path: &Path, url: &str) -> PathBuf {
+    let digest = format!("{:x}", Sha256::digest(url.as_bytes()));
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(format!(".{}.part", &digest[..PARTIAL_DIGEST_LEN]));
+    path.with_file_name(name)
+}
+
+/// Removes the temporary files of downloads into `path` other than `partial`
+async fn remove_stale_partials(path: &Path, partial: &Path) {
+    let prefix = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => format!("{}.", name),
+        None => return,
+    };
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let name = entry.file_name();
+        let digest = name
+            .to_str()
+            .and_then(|name| name.strip_prefix(prefix.as_str()))
+            .and_then(|rest| rest.strip_suffix(".part"));
+        let stale = match digest {
+            Some(digest) => {
+                digest.len() == PARTIAL_DIGEST_LEN
+                    && digest.bytes().all(|b| b.is_ascii_hexdigit())
+                    && Some(name.as_os_str()) != partial.file_name()
+            }
+            None => false,
+        };
+        if stale {
+            warn!("removing the partial download {} of another URL", entry.path().display());
+            let _ = tokio::fs::remove_file(entry.path()).await;
+        }
+    }
+}
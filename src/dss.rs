@@ -1,19 +1,132 @@
 use std::collections::HashMap;
+use std::path::Path;
+
+use crate::source::file_name;
+use crate::{ClientError, Labels, ModelSource};
 
 pub struct OptimizeRequest {
     pub filename: String,
-    pub source: Vec<u8>,
+    pub source: ModelSource,
+    pub labels: Labels,
 }
 
 pub struct CalibrateRequest {
     pub filename: String,
-    pub source: Vec<u8>,
+    pub source: ModelSource,
     pub input_tensors: Vec<String>,
+    pub labels: Labels,
 }
 
 pub struct QuantizeRequest {
     pub filename: String,
-    pub source: Vec<u8>,
+    pub source: ModelSource,
     pub input_tensors: Vec<String>,
     pub dynamic_ranges: HashMap<String, (f32, f32)>,
+    pub labels: Labels,
+}
+
+impl OptimizeRequest {
+    pub fn new<S: Into<ModelSource>>(source: S) -> OptimizeRequest {
+        OptimizeRequest {
+            filename: String::from("noname"),
+            source: source.into(),
+            labels: Labels::new(),
+        }
+    }
+
+    /// Creates a request which streams the model file named after the file
+    pub fn from_path<P: AsRef<Path>>(path: P) -> OptimizeRequest {
+        OptimizeRequest::new(ModelSource::from_path(&path)).filename(&file_name(&path))
+    }
+
+    pub fn filename(mut self, filename: &str) -> OptimizeRequest {
+        self.filename = String::from(filename);
+        self
+    }
+
+    /// Adds a label, which is validated when the request is sent
+    pub fn label(mut self, key: &str, value: &str) -> OptimizeRequest {
+        self.labels.insert(key.to_string(), value.to_string());
+        self
+    }
+}
+
+impl CalibrateRequest {
+    /// Creates a request whose `input_tensors` are the graph inputs of the ONNX model
+    pub fn from_model<S: Into<ModelSource>>(source: S) -> Result<CalibrateRequest, ClientError> {
+        let source = source.into();
+        let input_tensors = source.input_tensors()?;
+        Ok(CalibrateRequest {
+            filename: String::from("noname"),
+            source,
+            input_tensors,
+            labels: Labels::new(),
+        })
+    }
+
+    /// Reads the graph inputs of an ONNX model file and creates a request named after the file.
+    /// Only the graph inputs are read, and the model is streamed when the request is sent.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<CalibrateRequest, ClientError> {
+        Ok(CalibrateRequest::from_model(ModelSource::from_path(&path))?.filename(&file_name(&path)))
+    }
+
+    pub fn filename(mut self, filename: &str) -> CalibrateRequest {
+        self.filename = String::from(filename);
+        self
+    }
+
+    /// Adds a label, which is validated when the request is sent
+    pub fn label(mut self, key: &str, value: &str) -> CalibrateRequest {
+        self.labels.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn input_tensors(mut self, input_tensors: Vec<String>) -> CalibrateRequest {
+        self.input_tensors = input_tensors;
+        self
+    }
+}
+
+impl QuantizeRequest {
+    /// Creates a request whose `input_tensors` are the graph inputs of the ONNX model
+    pub fn from_model<S: Into<ModelSource>>(
+        source: S,
+        dynamic_ranges: HashMap<String, (f32, f32)>,
+    ) -> Result<QuantizeRequest, ClientError> {
+        let source = source.into();
+        let input_tensors = source.input_tensors()?;
+        Ok(QuantizeRequest {
+            filename: String::from("noname"),
+            source,
+            input_tensors,
+            dynamic_ranges,
+            labels: Labels::new(),
+        })
+    }
+
+    /// Reads the graph inputs of an ONNX model file and creates a request named after the file.
+    /// Only the graph inputs are read, and the model is streamed when the request is sent.
+    pub fn from_path<P: AsRef<Path>>(
+        path: P,
+        dynamic_ranges: HashMap<String, (f32, f32)>,
+    ) -> Result<QuantizeRequest, ClientError> {
+        let request = QuantizeRequest::from_model(ModelSource::from_path(&path), dynamic_ranges)?;
+        Ok(request.filename(&file_name(&path)))
+    }
+
+    pub fn filename(mut self, filename: &str) -> QuantizeRequest {
+        self.filename = String::from(filename);
+        self
+    }
+
+    /// Adds a label, which is validated when the request is sent
+    pub fn label(mut self, key: &str, value: &str) -> QuantizeRequest {
+        self.labels.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn input_tensors(mut self, input_tensors: Vec<String>) -> QuantizeRequest {
+        self.input_tensors = input_tensors;
+        self
+    }
 }
@@ -0,0 +1,55 @@
+//! User labels attached to compile and DSS requests
+//!
+//! Labels are key/value pairs (e.g., a git sha, a model version or a team) sent with each
+//! submission. The server echoes the labels of a compile task back on `CompileTask`, so that
+//! tasks can be listed by labels and traced back to their sources.
+
+use std::collections::BTreeMap;
+
+use reqwest::multipart::Form;
+
+use crate::ClientError;
+
+pub type Labels = BTreeMap<String, String>;
+
+static LABELS_PART_NAME: &str = "labels";
+pub static MAX_LABELS: usize = 64;
+pub static MAX_LABEL_KEY_LEN: usize = 63;
+pub static MAX_LABEL_VALUE_LEN: usize = 255;
+
+/// Keys start with an alphanumeric character followed by alphanumerics, '-', '_', '.' or '/'.
+/// Values may be empty and also allow ':', '+' and '@'.
+pub fn validate_labels(labels: &Labels) -> Result<(), ClientError> {
+    if labels.len() > MAX_LABELS {
+        return Err(ClientError::InvalidLabel(format!(
+            "{} labels are given but at most {} are allowed",
+            labels.len(),
+            MAX_LABELS
+        )));
+    }
+    for (key, value) in labels {
+        let valid_key = key.len() <= MAX_LABEL_KEY_LEN
+            && key.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && key.chars().all(|c| c.is_ascii_alphanumeric() || "-_./".contains(c));
+        if !valid_key {
+            return Err(ClientError::InvalidLabel(format!("invalid key '{}'", key)));
+        }
+        let valid_value = value.len() <= MAX_LABEL_VALUE_LEN
+            && value.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:+@".contains(c));
+        if !valid_value {
+            return Err(ClientError::InvalidLabel(format!(
+                "invalid value '{}' of '{}'",
+                value, key
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Adds the validated labels as a JSON object part unless they are empty
+pub(crate) fn add_to_form(form: Form, labels: &Labels) -> Form {
+    if labels.is_empty() {
+        return form;
+    }
+    form.text(LABELS_PART_NAME, serde_json::to_string(labels).unwrap())
+}
@@ -10,29 +10,77 @@
 //! FURIOSA_SECRET_ACCESS_KEY=YYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYY
 //! ```
 
+use std::collections::BTreeMap;
 use std::env::VarError;
+use std::future::Future;
 use std::io;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use bytes::Bytes;
 use lazy_static::lazy_static;
-use log::{error, info};
+use reqwest::header::{ACCEPT, USER_AGENT};
 use reqwest::multipart::{Form, Part};
-use reqwest::{RequestBuilder, Response};
+use reqwest::{RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWrite;
+use tracing::field::Empty;
+use tracing::{debug, info, instrument, warn, Instrument, Span};
 use uuid::Uuid;
 
-pub use crate::compile::{CompileRequest, TargetIr};
-use crate::compile::{CompileTask, CompileTaskPhase};
+pub use crate::artifact::{CompileOutput, CompileTimings, CompiledModel};
+use crate::batch::SubmittedTasks;
+pub use crate::batch::{BatchMode, BatchOptions};
+use crate::cache::InputHasher;
+pub use crate::cache::{CachePolicy, CompileCache};
+pub use crate::capability::Capabilities;
+use crate::cassette::CassetteTransport;
+pub use crate::cassette::{
+    Cassette, CassetteMode, FURIOSA_CASSETTE_ENV, FURIOSA_CASSETTE_MODE_ENV,
+};
+pub use crate::compile::{
+    CompileRequest, CompileTask, CompileTaskPhase, TargetIr, TaskFilter, TaskPage,
+};
+pub use crate::config::{current_profile, Profile, ResolvedConfig, FURIOSA_PROFILE_ENV};
 pub use crate::dss::{CalibrateRequest, OptimizeRequest, QuantizeRequest};
+pub use crate::labels::{
+    validate_labels, Labels, MAX_LABELS, MAX_LABEL_KEY_LEN, MAX_LABEL_VALUE_LEN,
+};
+pub use crate::metrics::{Metric, MetricsSink};
+pub use crate::operators::{check_operators, ModelFormat, OperatorIssue, OperatorReport};
+use crate::ratelimit::TokenBucket;
+pub use crate::ratelimit::{QuotaInfo, RateLimit};
+pub use crate::source::ModelSource;
+pub use crate::transport::{ReqwestTransport, Transport, TransportFuture};
+pub use crate::version::{Compatibility, CompatibilityStatus};
 use crate::ClientError::{ApiError, CompilationFailed};
 use semver::Version;
 
+mod artifact;
+mod batch;
 #[cfg(feature = "blocking")]
 pub mod blocking;
+mod cache;
+mod capability;
+mod cassette;
 mod compile;
+mod config;
+mod download;
 mod dss;
+mod labels;
+mod metrics;
+mod onnx;
+mod operators;
+mod ratelimit;
+mod source;
+#[cfg(feature = "otel")]
+mod telemetry;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod tflite;
+mod transport;
+mod version;
 
 pub static FURIOSA_API_ENDPOINT_ENV: &str = "FURIOSA_API_ENDPOINT";
 static ACCESS_KEY_ID_ENV: &str = "FURIOSA_ACCESS_KEY_ID";
@@ -43,6 +91,8 @@ static APPLICATION_OCTET_STREAM_MIME: &str = "application/octet-stream";
 static ACCESS_KEY_ID_HTTP_HEADER: &str = "X-FuriosaAI-Access-Key-ID";
 static SECRET_ACCESS_KEY_HTTP_HEADER: &str = "X-FuriosaAI-Secret-Access-KEY";
 static REQUEST_ID_HTTP_HEADER: &str = "X-Request-Id";
+static DEFAULT_POLL_INTERVAL_MS: u64 = 500;
+static MAX_RATE_LIMITED_ATTEMPTS: usize = 5;
 static FURIOSA_SDK_VERSION_HEADER: &str = "X-FuriosaAI-SDK-Version";
 
 lazy_static! {
@@ -73,18 +123,70 @@ pub enum ClientError {
     NoApiKey,
     #[error("ApiError: {0}")]
     ApiError(String),
+    /// The server failed with a 5xx status
+    #[error("Server error ({0}): {1}")]
+    ServerError(u16, String),
+    /// The request couldn't be sent or the response couldn't be received, e.g., by timeouts
+    #[error("Transport error: {0}")]
+    Transport(String),
     #[error("Compilation failed:\n{0}")]
     CompilationFailed(String),
     #[error("Invalid runtime version:\n{0}")]
     InvalidRuntimeVersion(String),
+    #[error("Unsupported by the server: {0}")]
+    Unsupported(String),
+    #[error("Incompatible server: {0}")]
+    IncompatibleServer(String),
     #[error("Invalid target ir:\n{0}")]
     InvalidTargetIr(String),
+    #[error("Invalid label: {0}")]
+    InvalidLabel(String),
+    #[error("Invalid model: {0}")]
+    InvalidModel(String),
+    #[error("Download failed: {0}")]
+    Download(String),
+    #[error("Checksum mismatch (expected: {0}, actual: {1})")]
+    ChecksumMismatch(String, String),
+    #[error("Unsupported NPU spec: {0}")]
+    UnsupportedNpuSpec(String),
+    #[error("Cassette error: {0}")]
+    Cassette(String),
+    #[error("Rate limited: {0} (retry after: {1:?})")]
+    RateLimited(String, Option<Duration>),
+    #[error("Blocking client called from a thread running a tokio runtime")]
+    NestedRuntime,
 }
 
 impl ClientError {
     pub fn io_error(kind: io::ErrorKind, msg: &str) -> ClientError {
         ClientError::Io(io::Error::new(kind, msg.to_string()))
     }
+
+    /// Returns the name of the variant, which is used as a metric label
+    pub fn code(&self) -> &'static str {
+        match self {
+            ClientError::Io(_) => "io",
+            ClientError::ConfigParse(..) => "config_parse",
+            ClientError::ConfigEnvVar(_) => "config_env_var",
+            ClientError::NoApiKey => "no_api_key",
+            ClientError::ApiError(_) => "api_error",
+            ClientError::ServerError(..) => "server_error",
+            ClientError::Transport(_) => "transport",
+            ClientError::CompilationFailed(_) => "compilation_failed",
+            ClientError::InvalidRuntimeVersion(_) => "invalid_runtime_version",
+            ClientError::Unsupported(_) => "unsupported",
+            ClientError::IncompatibleServer(_) => "incompatible_server",
+            ClientError::InvalidTargetIr(_) => "invalid_target_ir",
+            ClientError::InvalidLabel(_) => "invalid_label",
+            ClientError::InvalidModel(_) => "invalid_model",
+            ClientError::Download(_) => "download",
+            ClientError::ChecksumMismatch(..) => "checksum_mismatch",
+            ClientError::UnsupportedNpuSpec(_) => "unsupported_npu_spec",
+            ClientError::Cassette(_) => "cassette",
+            ClientError::RateLimited(..) => "rate_limited",
+            ClientError::NestedRuntime => "nested_runtime",
+        }
+    }
 }
 
 impl From<dotenv::Error> for ClientError {
@@ -118,6 +220,16 @@ pub struct FuriosaClient {
     access_key_id: String,
     secret_access_key: String,
     runtime_version: String,
+    compile_cache: Option<CompileCache>,
+    capabilities: Option<Capabilities>,
+    poll_interval: Duration,
+    transport: Arc<dyn Transport>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    submit_limiter: Option<TokenBucket>,
+    poll_limiter: Option<TokenBucket>,
+    quota: Mutex<Option<QuotaInfo>>,
+    /// Fetched by the first compilation to record it in `CompiledModel`
+    known_server_version: Mutex<Option<VersionInfo>>,
 }
 
 fn config_file_path(file: &str) -> Option<PathBuf> {
@@ -167,7 +279,15 @@ pub fn get_endpoint_from_env() -> Result<String, ClientError> {
     }
 }
 
+/// Servers without paging return an array of tasks
 #[derive(Deserialize)]
+#[serde(untagged)]
+enum TaskList {
+    Page(TaskPage),
+    Tasks(Vec<CompileTask>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct VersionInfo {
     pub version: String,
     pub revision: String,
@@ -176,29 +296,156 @@ pub struct VersionInfo {
 
 impl FuriosaClient {
     pub fn new<S: AsRef<str>>(runtime_version: S) -> Result<FuriosaClient, ClientError> {
+        let profile = config::current_profile();
         // Try to read $HOME/.furiosa/config including extra configurations
-        load_config_file("config")?;
+        load_config_file(&config::profile_file_name("config", &profile))?;
         // Try to read $HOME/.furiosa/credential and set credentials to environment variables
-        load_config_file("credential")?;
+        load_config_file(&config::profile_file_name("credential", &profile))?;
+
+        // Replaying a cassette doesn't need API keys
+        let cassette = Cassette::from_env()?;
+        let replay = cassette.as_ref().map(Cassette::mode) == Some(CassetteMode::Replay);
+        let api_key = |env: &str| match std::env::var(env) {
+            Ok(key) => Ok(key),
+            Err(_) if replay => Ok(String::new()),
+            Err(_) => Err(ClientError::NoApiKey),
+        };
+
+        // Try to get both API KEYs and exist if KEYs are not set
+        let access_key_id = api_key(ACCESS_KEY_ID_ENV)?;
+        let secret_access_key = api_key(SECRET_ACCESS_KEY_ENV)?;
+
+        let endpoint = get_endpoint_from_env()?;
+        let client = FuriosaClient::with_credential(
+            runtime_version,
+            endpoint,
+            access_key_id,
+            secret_access_key,
+        )?;
+        Ok(match cassette {
+            Some(cassette) => client.cassette(cassette),
+            None => client,
+        })
+    }
 
+    /// Creates a client with the given endpoint and API keys without reading the environment
+    /// variables and the files in `$HOME/.furiosa`
+    pub fn with_credential<S, E, K, V>(
+        runtime_version: S,
+        endpoint: E,
+        access_key_id: K,
+        secret_access_key: V,
+    ) -> Result<FuriosaClient, ClientError>
+    where
+        S: AsRef<str>,
+        E: Into<String>,
+        K: Into<String>,
+        V: Into<String>,
+    {
         let runtime_version = match Version::parse(runtime_version.as_ref()) {
             Ok(ver) => format!("{}.{}.{}", ver.major, ver.minor, ver.patch),
             Err(e) => return Err(ClientError::InvalidRuntimeVersion(format!("{}", e))),
         };
+        let endpoint = endpoint.into();
+        let access_key_id = access_key_id.into();
+        let secret_access_key = secret_access_key.into();
 
-        // Try to get both API KEYs and exist if KEYs are not set
-        let access_key_id = std::env::var(ACCESS_KEY_ID_ENV).map_err(|_| ClientError::NoApiKey)?;
-        let secret_access_key =
-            std::env::var(SECRET_ACCESS_KEY_ENV).map_err(|_| ClientError::NoApiKey)?;
+        // Only builds requests, which are sent by the transport
+        let client = reqwest::Client::new();
 
-        let endpoint = get_endpoint_from_env()?;
-        let client = reqwest::Client::builder()
-            .user_agent(FURIOSA_CLIENT_USER_AGENT.as_str())
-            .build()
-            .expect("fail to create HTTP Client");
+        info!(endpoint = endpoint.as_str(), "Connecting API Endpoint");
+        Ok(FuriosaClient {
+            client,
+            endpoint,
+            access_key_id,
+            secret_access_key,
+            runtime_version,
+            compile_cache: None,
+            capabilities: None,
+            poll_interval: Duration::from_millis(DEFAULT_POLL_INTERVAL_MS),
+            transport: Arc::new(ReqwestTransport::new()),
+            metrics: None,
+            submit_limiter: None,
+            poll_limiter: None,
+            quota: Mutex::new(None),
+            known_server_version: Mutex::new(None),
+        })
+    }
+
+    /// Enables the local cache of compiled artifacts, which is disabled by default.
+    /// `CompileRequest::cache_policy` controls how each request uses the cache.
+    pub fn compile_cache(mut self, cache: CompileCache) -> FuriosaClient {
+        self.compile_cache = Some(cache);
+        self
+    }
+
+    /// Sets the interval of polling a compile task, which is 500ms by default
+    pub fn poll_interval(mut self, interval: Duration) -> FuriosaClient {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Sends requests through `transport` instead of the default `ReqwestTransport`
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> FuriosaClient {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Records the HTTP exchanges of the current transport into the cassette or replays them
+    /// from it. A transport set after this replaces the cassette.
+    pub fn cassette(mut self, cassette: Cassette) -> FuriosaClient {
+        self.transport = Arc::new(CassetteTransport::new(cassette, self.transport));
+        self
+    }
+
+    /// Reports the metrics of all operations to `sink`
+    pub fn metrics_sink<M: MetricsSink + 'static>(mut self, sink: M) -> FuriosaClient {
+        self.metrics = Some(Arc::new(sink));
+        self
+    }
+
+    /// Limits the rate of submitting compile tasks and DSS requests
+    pub fn submit_rate_limit(mut self, limit: RateLimit) -> FuriosaClient {
+        self.submit_limiter = Some(TokenBucket::new(limit));
+        self
+    }
+
+    /// Limits the rate of polling compile tasks
+    pub fn poll_rate_limit(mut self, limit: RateLimit) -> FuriosaClient {
+        self.poll_limiter = Some(TokenBucket::new(limit));
+        self
+    }
 
-        info!("Connecting API Endpoint: {}", &endpoint);
-        Ok(FuriosaClient { client, endpoint, access_key_id, secret_access_key, runtime_version })
+    /// Returns the rate limit and quota reported by the latest response which has them
+    pub fn quota(&self) -> Option<QuotaInfo> {
+        self.quota.lock().unwrap().clone()
+    }
+
+    fn record_metric(&self, metric: Metric) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&metric);
+        }
+    }
+
+    fn record_upload(&self, operation: &'static str, source: &ModelSource) {
+        if let Some(bytes) = source.size() {
+            self.record_metric(Metric::BytesUploaded { operation, bytes });
+        }
+    }
+
+    /// Runs an operation and reports its latency and error
+    async fn measure<T, F>(&self, operation: &'static str, f: F) -> Result<T, ClientError>
+    where
+        F: Future<Output = Result<T, ClientError>>,
+    {
+        let start = Instant::now();
+        let result = f.await;
+        let elapsed = start.elapsed();
+        self.record_metric(Metric::Latency { operation, elapsed, success: result.is_ok() });
+        if let Err(e) = &result {
+            self.record_metric(Metric::Error { operation, code: e.code() });
+        }
+        result
     }
 
     fn set_default_headers(&self, b: RequestBuilder) -> RequestBuilder {
@@ -207,6 +454,75 @@ impl FuriosaClient {
             .header(FURIOSA_SDK_VERSION_HEADER, self.runtime_version.clone())
     }
 
+    /// Sends a request with a new request ID unless it already has one
+    async fn send(&self, builder: RequestBuilder) -> Result<Response, ClientError> {
+        let mut request = builder
+            .header(USER_AGENT, FURIOSA_CLIENT_USER_AGENT.as_str())
+            .build()
+            .map_err(|e| ApiError(format!("{}", e)))?;
+        let request_id = match request.headers().get(REQUEST_ID_HTTP_HEADER) {
+            Some(id) => id.to_str().unwrap_or_default().to_string(),
+            None => {
+                let id = Uuid::new_v4().to_hyphenated().to_string();
+                request.headers_mut().insert(REQUEST_ID_HTTP_HEADER, id.parse().unwrap());
+                id
+            }
+        };
+
+        let span = tracing::debug_span!(
+            "http_request",
+            method = %request.method(),
+            path = request.url().path(),
+            request_id = request_id.as_str(),
+            status = Empty,
+        );
+        #[cfg(feature = "otel")]
+        telemetry::inject_context(&span, request.headers_mut());
+
+        async move {
+            let mut request = request;
+            let mut attempt = 1;
+            loop {
+                let retry = request.try_clone();
+                let response = match self.transport.send(request).await {
+                    Ok(response) => response,
+                    Err(e) => {
+                        debug!(error = %e, "fail to send the request");
+                        return Err(e);
+                    }
+                };
+                Span::current().record("status", response.status().as_u16());
+                debug!(status = response.status().as_u16(), "received the response");
+                if let Some(quota) = QuotaInfo::from_headers(response.headers()) {
+                    *self.quota.lock().unwrap() = Some(quota);
+                }
+                if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                    return Ok(response);
+                }
+
+                let retry_after = ratelimit::retry_after(response.headers());
+                match (retry, ratelimit::retry_wait(retry_after)) {
+                    (Some(retry), Some(wait)) if attempt < MAX_RATE_LIMITED_ATTEMPTS => {
+                        warn!(attempt, wait = ?wait, "rate limited by the server");
+                        self.record_metric(Metric::Retry { operation: "rate_limited" });
+                        tokio::time::sleep(wait).await;
+                        request = retry;
+                        attempt += 1;
+                    }
+                    _ => {
+                        let message = match response.json::<ApiResponse>().await {
+                            Ok(response) => response.message,
+                            Err(_) => String::from("too many requests"),
+                        };
+                        return Err(ClientError::RateLimited(message, retry_after));
+                    }
+                }
+            }
+        }
+        .instrument(span)
+        .await
+    }
+
     #[inline]
     fn api_root_path(&self, path: &str) -> String {
         format!("{}/{}", &self.endpoint, path)
@@ -226,214 +542,858 @@ impl FuriosaClient {
         &self.endpoint
     }
 
+    #[instrument(skip(self))]
     pub async fn server_version(&self) -> Result<VersionInfo, ClientError> {
-        let path = &self.api_root_path("version");
-        let response = self.client.get(path).send().await;
-        make_response(path, response, |bytes| Ok(serde_json::from_slice(&bytes).unwrap())).await
+        self.measure("server_version", async {
+            let path = &self.api_root_path("version");
+            let response = self.send(self.client.get(path)).await;
+            make_response(path, response, |bytes| Ok(serde_json::from_slice(&bytes).unwrap())).await
+        })
+        .await
     }
 
-    pub async fn compile(&self, request: CompileRequest) -> Result<Box<[u8]>, ClientError> {
-        let mut model_image = Part::bytes(request.source);
-        model_image = model_image.file_name(request.filename);
+    /// Returns the features which the server supports
+    #[instrument(skip(self))]
+    pub async fn capabilities(&self) -> Result<Capabilities, ClientError> {
+        self.measure("capabilities", async {
+            let path = &self.api_root_path("capabilities");
+            let response = self.send(self.set_default_headers(self.client.get(path))).await;
+            make_response(path, response, |bytes| {
+                serde_json::from_slice(&bytes)
+                    .map_err(|e| ApiError(format!("fail to parse the capabilities: {}", e)))
+            })
+            .await
+        })
+        .await
+    }
 
-        model_image =
-            model_image.mime_str(APPLICATION_OCTET_STREAM_MIME).expect("Invalid MIME type");
+    /// Fetches the capabilities of the server, so that following requests which the server
+    /// doesn't support fail early with `ClientError::Unsupported`
+    pub async fn discover_capabilities(mut self) -> Result<FuriosaClient, ClientError> {
+        self.capabilities = Some(self.capabilities().await?);
+        Ok(self)
+    }
 
-        let mut form: Form = Form::new()
-            .text(TARGET_IR_PART_NAME, request.target_ir.as_str().to_string())
-            .text(
-                TARGET_NPU_SPEC_PART_NAME,
-                serde_json::to_string(&request.target_npu_spec).unwrap(),
-            )
-            .part(SOURCE_PART_NAME, model_image);
+    /// Returns the capabilities fetched by `discover_capabilities`
+    pub fn discovered_capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_ref()
+    }
+
+    fn check_dss(&self, operation: &str, source: &ModelSource) -> Result<(), ClientError> {
+        match &self.capabilities {
+            Some(capabilities) => capabilities.check_dss(operation, source),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks if the server version supports the runtime version of the client
+    pub async fn check_compatibility(&self) -> Result<Compatibility, ClientError> {
+        let server_version = self.server_version().await?;
+        Compatibility::check(&self.runtime_version, &server_version.version)
+    }
+
+    /// Fails with `ClientError::IncompatibleServer` if the server is older than the versions
+    /// supporting the runtime version of the client. It warns and passes if the compatibility
+    /// is unknown, which includes servers newer than the listed versions.
+    pub async fn require_compatible_server(self) -> Result<FuriosaClient, ClientError> {
+        self.check_required_compatibility().await?;
+        Ok(self)
+    }
+
+    async fn check_required_compatibility(&self) -> Result<(), ClientError> {
+        let compatibility = self.check_compatibility().await?;
+        match compatibility.status {
+            CompatibilityStatus::Compatible => Ok(()),
+            CompatibilityStatus::Unknown => {
+                warn!(
+                    "Unknown compatibility between SDK {} and server {} (listed: {})",
+                    &compatibility.sdk_version,
+                    &compatibility.server_version,
+                    compatibility.required_server_version.as_deref().unwrap_or("none")
+                );
+                Ok(())
+            }
+            CompatibilityStatus::Incompatible => Err(ClientError::IncompatibleServer(format!(
+                "SDK {} requires server {}, but the server is {}",
+                &compatibility.sdk_version,
+                compatibility.required_server_version.unwrap_or_default(),
+                &compatibility.server_version
+            ))),
+        }
+    }
+
+    #[instrument(
+        skip(self, request),
+        fields(
+            request_id = Empty,
+            task_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+            target_ir = request.target_ir.as_str(),
+        )
+    )]
+    pub async fn compile(&self, request: CompileRequest) -> Result<CompiledModel, ClientError> {
+        self.compile_tracked(request, None).await
+    }
+
+    /// Compiles a model, keeping the ID of the submitted task in `submitted` until it completes
+    pub(crate) async fn compile_tracked(
+        &self,
+        request: CompileRequest,
+        submitted: Option<&SubmittedTasks>,
+    ) -> Result<CompiledModel, ClientError> {
+        self.measure("compile", async {
+            let mut hasher = InputHasher::new(&request, &self.runtime_version);
+            let mut model = CompiledModel {
+                bytes: Box::default(),
+                target_ir: request.target_ir,
+                extra_artifacts: BTreeMap::new(),
+                filename: request.filename.clone(),
+                labels: request.labels.clone(),
+                runtime_version: self.runtime_version.clone(),
+                task_id: None,
+                server_version: None,
+                timings: None,
+                input_hash: None,
+            };
+            let cache = match (&self.compile_cache, request.cache_policy) {
+                (Some(cache), policy) if policy != CachePolicy::Bypass => Some(cache),
+                _ => None,
+            };
+            let mut keys = None;
+            if let (Some(cache), CachePolicy::Use) = (cache, request.cache_policy) {
+                // looking up the cache needs the hash before uploading, so the source is read
+                // twice. Otherwise, it's hashed while uploading.
+                if let Some(h) = hasher.as_mut() {
+                    if let Err(e) = h.hash_source(&request.source) {
+                        warn!("fail to read the source for the compile cache: {}", e);
+                        hasher = None;
+                    }
+                }
+                keys = hasher.as_ref().and_then(InputHasher::keys);
+                if let Some(keys) = &keys {
+                    // uses the cache only if all the requested artifacts are cached
+                    let cached: Option<Vec<_>> = keys
+                        .iter()
+                        .map(|(target_ir, key)| cache.get(key).map(|a| (*target_ir, a)))
+                        .collect();
+                    if let Some(artifacts) = cached {
+                        info!(key = keys[0].1.as_str(), "Using the cached artifact");
+                        model.input_hash = Some(keys[0].1.clone());
+                        model.set_artifacts(artifacts)?;
+                        return Ok(model);
+                    }
+                }
+            }
+
+            let upload_hasher = match keys {
+                Some(_) => None,
+                None => hasher.map(|h| Arc::new(Mutex::new(h))),
+            };
+            let (task, artifacts) =
+                self.compile_remote(request, upload_hasher.clone(), submitted).await?;
+            let keys = keys.or_else(|| upload_hasher.and_then(|h| h.lock().unwrap().keys()));
+            if let (Some(cache), Some(keys)) = (cache, &keys) {
+                for ((_, key), (_, artifact)) in keys.iter().zip(&artifacts) {
+                    if let Err(e) = cache.put(key, artifact) {
+                        warn!("fail to store the artifact in the compile cache: {}", e);
+                    }
+                }
+            }
+            model.input_hash = keys.map(|mut keys| keys.swap_remove(0).1);
+            model.set_artifacts(artifacts)?;
+            model.timings = Some(CompileTimings::from(&task));
+            model.task_id = Some(task.task_id);
+            model.server_version = self.known_server_version().await;
+            Ok(model)
+        })
+        .await
+    }
 
-        if let Some(compiler_config) = &request.compiler_config {
+    /// Returns the artifacts in the order of `CompileRequest::requested_target_irs`
+    async fn compile_remote(
+        &self,
+        request: CompileRequest,
+        hasher: Option<Arc<Mutex<InputHasher>>>,
+        submitted: Option<&SubmittedTasks>,
+    ) -> Result<(CompileTask, Vec<(TargetIr, Box<[u8]>)>), ClientError> {
+        let target_irs = request.requested_target_irs();
+        let task = self.run_compile_task(request, hasher, submitted).await?;
+        let mut artifacts = Vec::with_capacity(target_irs.len());
+        for target_ir in target_irs {
+            let path = self.artifact_path(&task.task_id, target_ir);
+            let request = self.client.get(&path).header(ACCEPT, target_ir.content_type());
+            let response = self.send(self.set_default_headers(request)).await;
+            if let Ok(response) = &response {
+                check_artifact_status(target_ir, response.status())?;
+            }
+            let artifact: Box<[u8]> =
+                make_response(&path, response, |bytes| Ok(bytes.to_vec().into_boxed_slice()))
+                    .await?;
+            let bytes = artifact.len() as u64;
+            self.record_metric(Metric::BytesDownloaded { operation: "compile", bytes });
+            artifacts.push((target_ir, artifact));
+        }
+        Ok((task, artifacts))
+    }
+
+    /// Returns the server version fetched once per client, or `None` if it can't be fetched
+    async fn known_server_version(&self) -> Option<VersionInfo> {
+        if let Some(version) = self.known_server_version.lock().unwrap().clone() {
+            return Some(version);
+        }
+        match self.server_version().await {
+            Ok(version) => {
+                *self.known_server_version.lock().unwrap() = Some(version.clone());
+                Some(version)
+            }
+            Err(e) => {
+                warn!("fail to fetch the server version: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Compiles a model and streams the artifact into `writer` without keeping it in memory.
+    /// It doesn't use the compile cache, and only writes the artifact of `target_ir`.
+    /// Returns the number of written bytes.
+    #[instrument(
+        skip(self, request, writer),
+        fields(
+            request_id = Empty,
+            task_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+            target_ir = request.target_ir.as_str(),
+        )
+    )]
+    pub async fn compile_to_writer<W>(
+        &self,
+        request: CompileRequest,
+        writer: &mut W,
+    ) -> Result<u64, ClientError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.measure("compile", async {
+            let target_ir = request.target_ir;
+            let task = self.run_compile_task(request, None, None).await?;
+            let path = self.artifact_path(&task.task_id, target_ir);
+            self.download("compile", target_ir, &path, writer, 0, Default::default()).await
+        })
+        .await
+    }
+
+    /// Compiles a model and streams the artifact into the file at `path`, which is replaced
+    /// atomically once the download completes. The artifacts of `extra_target_irs` are streamed
+    /// into `CompiledModel::extra_artifact_path`. It doesn't use the compile cache.
+    /// Returns the number of written bytes.
+    #[instrument(
+        skip(self, request, path),
+        fields(
+            request_id = Empty,
+            task_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+            target_ir = request.target_ir.as_str(),
+            output = %path.as_ref().display(),
+        )
+    )]
+    pub async fn compile_to_path<P: AsRef<Path>>(
+        &self,
+        request: CompileRequest,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.measure("compile", async {
+            let path = path.as_ref();
+            let target_irs = request.requested_target_irs();
+            let task = self.run_compile_task(request, None, None).await?;
+            let mut written = 0;
+            for (i, target_ir) in target_irs.into_iter().enumerate() {
+                let url = self.artifact_path(&task.task_id, target_ir);
+                let output = if i == 0 {
+                    path.to_path_buf()
+                } else {
+                    CompiledModel::extra_artifact_path(path, target_ir)
+                };
+                written += self.download_to_path("compile", target_ir, &url, &output).await?;
+            }
+            Ok(written)
+        })
+        .await
+    }
+
+    /// Submits a compile task and waits for its completion, feeding the uploaded source into
+    /// `hasher` and keeping the task ID in `submitted` until the task completes if given.
+    /// Returns the completed task if the task succeeds.
+    async fn run_compile_task(
+        &self,
+        request: CompileRequest,
+        hasher: Option<Arc<Mutex<InputHasher>>>,
+        submitted: Option<&SubmittedTasks>,
+    ) -> Result<CompileTask, ClientError> {
+        if let Some(capabilities) = &self.capabilities {
+            capabilities.check_compile(&request)?;
+        }
+        validate_labels(&request.labels)?;
+        self.record_upload("compile", &request.source);
+        let target_irs = request.requested_target_irs();
+        let CompileRequest { target_npu_spec, compiler_config, filename, source, labels, .. } =
+            request;
+
+        // A repeated `target_ir` part for each IR, where the first one is `target_ir`. The API
+        // documents a single part; a server which reads only the first one compiles `target_ir`,
+        // and the artifacts of the extra IRs fail to download with `ClientError::Unsupported`.
+        let make_form = |model_image: Part| {
+            let mut form: Form = Form::new();
+            for target_ir in &target_irs {
+                form = form.text(TARGET_IR_PART_NAME, target_ir.as_str().to_string());
+            }
             form = form
-                .text(COMPILER_CONFIG_PART_NAME, serde_json::to_string(compiler_config).unwrap());
+                .text(TARGET_NPU_SPEC_PART_NAME, serde_json::to_string(&target_npu_spec).unwrap())
+                .part(SOURCE_PART_NAME, model_image);
+            form = labels::add_to_form(form, &labels);
+
+            if let Some(compiler_config) = &compiler_config {
+                form = form.text(
+                    COMPILER_CONFIG_PART_NAME,
+                    serde_json::to_string(compiler_config).unwrap(),
+                );
+            };
+            form
         };
 
         let path = &self.api_v1alpha_path("compiler", "tasks");
-        let req = self
-            .client
-            .post(path)
-            .header(REQUEST_ID_HTTP_HEADER, Uuid::new_v4().to_hyphenated().to_string());
-        let response = self.set_default_headers(req).multipart(form).send().await;
+        let request_id = Uuid::new_v4().to_hyphenated().to_string();
+        Span::current().record("request_id", request_id.as_str());
+        let req = self.client.post(path).header(REQUEST_ID_HTTP_HEADER, &request_id);
+        let response =
+            self.send_multipart(req, source, &filename, hasher.as_ref(), make_form).await;
 
         let mut task: CompileTask =
             make_response(path, response, |bytes| Ok(serde_json::from_slice(&bytes).unwrap()))
                 .await?;
 
-        let task_id = task.task_id;
+        let task_id = task.task_id.clone();
+        Span::current().record("task_id", task_id.as_str());
+        info!(task_id = task_id.as_str(), phase = ?task.phase, "submitted the compile task");
+        if let Some(submitted) = submitted {
+            submitted.insert(&task_id);
+        }
 
+        let mut polls = 0;
         loop {
             if task.phase.is_completed() {
                 break;
             }
 
-            tokio::time::sleep(Duration::from_millis(500)).await;
-            let path = self.api_v1alpha_path("compiler", &format!("tasks/{}", &task_id));
-            let response = self.set_default_headers(self.client.get(&path)).send().await;
-            task =
-                make_response(&path, response, |bytes| Ok(serde_json::from_slice(&bytes).unwrap()))
-                    .await?;
+            tokio::time::sleep(self.poll_interval).await;
+            polls += 1;
+            debug!(task_id = task_id.as_str(), polls, "polling the compile task");
+            let phase = task.phase;
+            task = self.get_task(&task_id).await?;
+            if task.phase != phase {
+                info!(
+                    task_id = task_id.as_str(),
+                    phase = ?task.phase,
+                    progress = task.progress,
+                    "compile task phase changed"
+                );
+            }
+        }
+
+        if let Some(submitted) = submitted {
+            submitted.remove(&task_id);
+        }
+        if let Some((queue_time, run_time)) = metrics::task_times(&task) {
+            self.record_metric(Metric::QueueTime(queue_time));
+            self.record_metric(Metric::RunTime(run_time));
         }
 
         match &task.phase {
-            CompileTaskPhase::Succeeded => {
-                let path = self.api_v1alpha_path(
-                    "compiler",
-                    &format!("tasks/{}/artifacts/output.enf", &task_id),
-                );
-                let response = self.set_default_headers(self.client.get(&path)).send().await;
-                return make_response(&path, response, |bytes| {
-                    Ok(bytes.to_vec().into_boxed_slice())
-                })
-                .await;
-            }
-            CompileTaskPhase::Failed => {
-                let path = self.api_v1alpha_path("compiler", &format!("tasks/{}/logs", &task_id));
-                let response = self.set_default_headers(self.client.get(&path)).send().await;
-                let log_message: String = make_response(&path, response, |bytes| {
-                    Ok(String::from_utf8_lossy(&bytes).to_string())
-                })
-                .await?;
-                Err(CompilationFailed(log_message))
-            }
+            CompileTaskPhase::Succeeded => Ok(task),
+            CompileTaskPhase::Failed => Err(CompilationFailed(self.task_logs(&task_id).await?)),
             _ => unreachable!("cannot reach non-terminal phase"),
         }
     }
 
-    pub async fn optimize(&self, request: OptimizeRequest) -> Result<Box<[u8]>, ClientError> {
-        let mut model_image = Part::bytes(request.source);
-        model_image = model_image.file_name(request.filename);
+    fn artifact_path(&self, task_id: &str, target_ir: TargetIr) -> String {
+        let artifact = format!("tasks/{}/artifacts/{}", task_id, target_ir.artifact_name());
+        self.api_v1alpha_path("compiler", &artifact)
+    }
 
-        model_image =
-            model_image.mime_str(APPLICATION_OCTET_STREAM_MIME).expect("Invalid MIME type");
+    /// Fetches the artifact of `target_ir` from a compile task which has succeeded. Fails with
+    /// `ClientError::Unsupported` if the server has no artifact of an IR other than ENF, whose
+    /// name isn't confirmed (see `TargetIr::artifact_name`).
+    #[instrument(skip(self))]
+    pub async fn task_artifact(
+        &self,
+        task_id: &str,
+        target_ir: TargetIr,
+    ) -> Result<CompileOutput, ClientError> {
+        self.measure("task_artifact", async {
+            let path = self.artifact_path(task_id, target_ir);
+            let request = self.client.get(&path).header(ACCEPT, target_ir.content_type());
+            let response = self.send(self.set_default_headers(request)).await;
+            if let Ok(response) = &response {
+                check_artifact_status(target_ir, response.status())?;
+            }
+            make_response(&path, response, |bytes| {
+                Ok(CompileOutput::decode(target_ir, bytes.to_vec().into_boxed_slice()))
+            })
+            .await
+        })
+        .await
+    }
 
-        let form: Form = Form::new().part(SOURCE_PART_NAME, model_image);
-        let request = self
-            .client
-            .post(&self.api_v1_path("dss/optimize"))
-            .header(REQUEST_ID_HTTP_HEADER, Uuid::new_v4().to_hyphenated().to_string());
-        let response = self.set_default_headers(request).multipart(form).send().await;
+    /// Streams the artifact of `target_ir` from a compile task which has succeeded into the
+    /// file at `path`
+    #[instrument(skip(self, path))]
+    pub async fn task_artifact_to_path<P: AsRef<Path>>(
+        &self,
+        task_id: &str,
+        target_ir: TargetIr,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.measure("task_artifact", async {
+            let url = self.artifact_path(task_id, target_ir);
+            self.download_to_path("task_artifact", target_ir, &url, path.as_ref()).await
+        })
+        .await
+    }
 
-        match response {
-            Ok(res) => {
-                if res.status().is_success() {
-                    match res.bytes().await {
-                        Ok(bytes) => Ok(bytes.to_vec().into_boxed_slice()),
-                        Err(e) => {
-                            Err(ApiError(format!("fail to fetch the calibration onnx: {}", e)))
-                        }
-                    }
-                } else {
-                    let response: ApiResponse = match res.json().await {
-                        Ok(api_response) => api_response,
-                        Err(e) => return Err(ApiError(format!("fail to get API response: {}", e))),
-                    };
-                    Err(ApiError(format!("fail to compile: {}", &response.message)))
-                }
+    /// Returns a page of compile tasks submitted with the API key, which match `filter`
+    #[instrument(skip(self))]
+    pub async fn list_tasks(&self, filter: TaskFilter) -> Result<TaskPage, ClientError> {
+        self.measure("list_tasks", async {
+            let path = self.api_v1alpha_path("compiler", "tasks");
+            let request = self.client.get(&path).query(&filter.to_query());
+            let response = self.send(self.set_default_headers(request)).await;
+            let page: TaskList = make_response(&path, response, |bytes| {
+                serde_json::from_slice(&bytes)
+                    .map_err(|e| ApiError(format!("fail to parse the tasks: {}", e)))
+            })
+            .await?;
+            let mut page = match page {
+                TaskList::Page(page) => page,
+                TaskList::Tasks(tasks) => TaskPage { tasks, next_page_token: None },
+            };
+            page.tasks.retain(|task| filter.matches(task));
+            Ok(page)
+        })
+        .await
+    }
+
+    /// Returns all compile tasks which match `filter`, following the pages
+    pub async fn list_all_tasks(
+        &self,
+        filter: TaskFilter,
+    ) -> Result<Vec<CompileTask>, ClientError> {
+        let mut filter = filter;
+        let mut tasks = Vec::new();
+        loop {
+            let page = self.list_tasks(filter.clone()).await?;
+            tasks.extend(page.tasks);
+            match page.next_page_token {
+                Some(token) => filter.page_token = Some(token),
+                None => return Ok(tasks),
             }
-            Err(e) => Err(ApiError(format!("{}", e))),
         }
     }
 
+    #[instrument(skip(self))]
+    pub async fn get_task(&self, task_id: &str) -> Result<CompileTask, ClientError> {
+        self.measure("get_task", async {
+            let path = self.api_v1alpha_path("compiler", &format!("tasks/{}", task_id));
+            ratelimit::acquire(&self.poll_limiter).await;
+            let response = self.send(self.set_default_headers(self.client.get(&path))).await;
+            make_response(&path, response, |bytes| Ok(serde_json::from_slice(&bytes).unwrap()))
+                .await
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn cancel_task(&self, task_id: &str) -> Result<(), ClientError> {
+        self.measure("cancel_task", async {
+            let path = self.api_v1alpha_path("compiler", &format!("tasks/{}", task_id));
+            let response = self.send(self.set_default_headers(self.client.delete(&path))).await;
+            make_response(&path, response, |_| Ok(())).await
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    pub async fn task_logs(&self, task_id: &str) -> Result<String, ClientError> {
+        self.measure("task_logs", async {
+            let path = self.api_v1alpha_path("compiler", &format!("tasks/{}/logs", task_id));
+            let response = self.send(self.set_default_headers(self.client.get(&path))).await;
+            make_response(&path, response, |bytes| Ok(String::from_utf8_lossy(&bytes).to_string()))
+                .await
+        })
+        .await
+    }
+
+    #[instrument(
+        skip(self, request),
+        fields(
+            request_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+        )
+    )]
+    pub async fn optimize(&self, request: OptimizeRequest) -> Result<Box<[u8]>, ClientError> {
+        self.measure("optimize", async {
+            let response = self.send_optimize(request).await?;
+            read_dss_response(response, "calibration onnx").await
+        })
+        .await
+    }
+
+    /// Streams the optimized model into `writer` and returns the number of written bytes
+    #[instrument(
+        skip(self, request, writer),
+        fields(
+            request_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+        )
+    )]
+    pub async fn optimize_to_writer<W>(
+        &self,
+        request: OptimizeRequest,
+        writer: &mut W,
+    ) -> Result<u64, ClientError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.measure("optimize", async {
+            download::write_response(self.send_optimize(request).await?, writer).await
+        })
+        .await
+    }
+
+    /// Streams the optimized model into the file at `path` and returns the number of bytes
+    #[instrument(
+        skip(self, request, path),
+        fields(
+            request_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+        )
+    )]
+    pub async fn optimize_to_path<P: AsRef<Path>>(
+        &self,
+        request: OptimizeRequest,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.measure("optimize", async {
+            download::write_response_to_path(self.send_optimize(request).await?, path.as_ref())
+                .await
+        })
+        .await
+    }
+
+    async fn send_optimize(&self, request: OptimizeRequest) -> Result<Response, ClientError> {
+        self.check_dss("optimize", &request.source)?;
+        validate_labels(&request.labels)?;
+        self.record_upload("optimize", &request.source);
+        let OptimizeRequest { filename, source, labels } = request;
+        let make_form = |model_image: Part| {
+            let form: Form = Form::new().part(SOURCE_PART_NAME, model_image);
+            labels::add_to_form(form, &labels)
+        };
+        self.send_dss("dss/optimize", "optimize", source, &filename, make_form).await
+    }
+
+    #[instrument(
+        skip(self, request),
+        fields(
+            request_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+        )
+    )]
     pub async fn build_calibration_model(
         &self,
         request: CalibrateRequest,
     ) -> Result<Box<[u8]>, ClientError> {
-        let mut model_image = Part::bytes(request.source);
-        model_image = model_image.file_name(request.filename);
+        self.measure("build_calibration_model", async {
+            let response = self.send_build_calibration_model(request).await?;
+            read_dss_response(response, "calibration onnx").await
+        })
+        .await
+    }
 
-        model_image =
-            model_image.mime_str(APPLICATION_OCTET_STREAM_MIME).expect("Invalid MIME type");
+    /// Streams the calibration model into `writer` and returns the number of written bytes
+    #[instrument(
+        skip(self, request, writer),
+        fields(
+            request_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+        )
+    )]
+    pub async fn build_calibration_model_to_writer<W>(
+        &self,
+        request: CalibrateRequest,
+        writer: &mut W,
+    ) -> Result<u64, ClientError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.measure("build_calibration_model", async {
+            let response = self.send_build_calibration_model(request).await?;
+            download::write_response(response, writer).await
+        })
+        .await
+    }
 
-        let input_tensors = serde_json::to_string(&request.input_tensors).map_err(|_| {
+    /// Streams the calibration model into the file at `path` and returns the number of bytes
+    #[instrument(
+        skip(self, request, path),
+        fields(
+            request_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+        )
+    )]
+    pub async fn build_calibration_model_to_path<P: AsRef<Path>>(
+        &self,
+        request: CalibrateRequest,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.measure("build_calibration_model", async {
+            let response = self.send_build_calibration_model(request).await?;
+            download::write_response_to_path(response, path.as_ref()).await
+        })
+        .await
+    }
+
+    async fn send_build_calibration_model(
+        &self,
+        request: CalibrateRequest,
+    ) -> Result<Response, ClientError> {
+        self.check_dss("build-calibration-model", &request.source)?;
+        validate_labels(&request.labels)?;
+        self.record_upload("build_calibration_model", &request.source);
+        let CalibrateRequest { filename, source, input_tensors, labels } = request;
+
+        let input_tensors = serde_json::to_string(&input_tensors).map_err(|_| {
             ClientError::ApiError("Failed to serialize 'input_tenosrs'.".to_string())
         })?;
-        let form: Form = Form::new()
-            .text(DSS_INPUT_TENSORS_PART_NAME, input_tensors)
-            .part(SOURCE_PART_NAME, model_image);
-        let request = self
-            .client
-            .post(&self.api_v1_path("dss/build-calibration-model"))
-            .header(REQUEST_ID_HTTP_HEADER, Uuid::new_v4().to_hyphenated().to_string());
-        let response = self
-            .set_default_headers(request)
-            .header(ACCESS_KEY_ID_HTTP_HEADER, &self.access_key_id)
-            .header(SECRET_ACCESS_KEY_HTTP_HEADER, &self.secret_access_key)
-            .multipart(form)
-            .send()
-            .await;
-
-        match response {
-            Ok(res) => {
-                if res.status().is_success() {
-                    match res.bytes().await {
-                        Ok(bytes) => Ok(bytes.to_vec().into_boxed_slice()),
-                        Err(e) => {
-                            Err(ApiError(format!("fail to fetch the calibration onnx: {}", e)))
-                        }
-                    }
-                } else {
-                    let response: ApiResponse = match res.json().await {
-                        Ok(api_response) => api_response,
-                        Err(e) => return Err(ApiError(format!("fail to get API response: {}", e))),
-                    };
-                    Err(ApiError(format!("fail to compile: {}", &response.message)))
-                }
-            }
-            Err(e) => Err(ApiError(format!("{}", e))),
-        }
+        let make_form = |model_image: Part| {
+            let form: Form = Form::new()
+                .text(DSS_INPUT_TENSORS_PART_NAME, input_tensors.clone())
+                .part(SOURCE_PART_NAME, model_image);
+            labels::add_to_form(form, &labels)
+        };
+        let api = "dss/build-calibration-model";
+        self.send_dss(api, "build_calibration_model", source, &filename, make_form).await
     }
 
+    #[instrument(
+        skip(self, request),
+        fields(
+            request_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+        )
+    )]
     pub async fn quantize(&self, request: QuantizeRequest) -> Result<Box<[u8]>, ClientError> {
-        let mut model_image = Part::bytes(request.source);
-        model_image = model_image.file_name(request.filename);
+        self.measure("quantize", async {
+            let response = self.send_quantize(request).await?;
+            read_dss_response(response, "quantized onnx").await
+        })
+        .await
+    }
+
+    /// Streams the quantized model into `writer` and returns the number of written bytes
+    #[instrument(
+        skip(self, request, writer),
+        fields(
+            request_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+        )
+    )]
+    pub async fn quantize_to_writer<W>(
+        &self,
+        request: QuantizeRequest,
+        writer: &mut W,
+    ) -> Result<u64, ClientError>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        self.measure("quantize", async {
+            download::write_response(self.send_quantize(request).await?, writer).await
+        })
+        .await
+    }
+
+    /// Streams the quantized model into the file at `path` and returns the number of bytes
+    #[instrument(
+        skip(self, request, path),
+        fields(
+            request_id = Empty,
+            trace_id = Empty,
+            filename = request.filename.as_str(),
+            size = ?request.source.size(),
+        )
+    )]
+    pub async fn quantize_to_path<P: AsRef<Path>>(
+        &self,
+        request: QuantizeRequest,
+        path: P,
+    ) -> Result<u64, ClientError> {
+        self.measure("quantize", async {
+            download::write_response_to_path(self.send_quantize(request).await?, path.as_ref())
+                .await
+        })
+        .await
+    }
 
-        model_image =
-            model_image.mime_str(APPLICATION_OCTET_STREAM_MIME).expect("Invalid MIME type");
+    async fn send_quantize(&self, request: QuantizeRequest) -> Result<Response, ClientError> {
+        self.check_dss("quantize", &request.source)?;
+        validate_labels(&request.labels)?;
+        self.record_upload("quantize", &request.source);
+        let QuantizeRequest { filename, source, input_tensors, dynamic_ranges, labels } = request;
 
-        let input_tensors = serde_json::to_string(&request.input_tensors).map_err(|_| {
+        let input_tensors = serde_json::to_string(&input_tensors).map_err(|_| {
             ClientError::ApiError("Failed to serialize 'input_tensors'.".to_string())
         })?;
-        let dynamic_ranges = serde_json::to_string(&request.dynamic_ranges).map_err(|_| {
+        let dynamic_ranges = serde_json::to_string(&dynamic_ranges).map_err(|_| {
             ClientError::ApiError("Failed to serialize 'dynamic_ranges'.".to_string())
         })?;
-        let form: Form = Form::new()
-            .text(DSS_INPUT_TENSORS_PART_NAME, input_tensors)
-            .text(DSS_DYNAMIC_RANGES_PART_NAME, dynamic_ranges)
-            .part(SOURCE_PART_NAME, model_image);
+        let make_form = |model_image: Part| {
+            let form: Form = Form::new()
+                .text(DSS_INPUT_TENSORS_PART_NAME, input_tensors.clone())
+                .text(DSS_DYNAMIC_RANGES_PART_NAME, dynamic_ranges.clone())
+                .part(SOURCE_PART_NAME, model_image);
+            labels::add_to_form(form, &labels)
+        };
+        self.send_dss("dss/quantize", "quantize", source, &filename, make_form).await
+    }
 
-        let request = self
-            .client
-            .post(&self.api_v1_path("dss/quantize"))
-            .header(REQUEST_ID_HTTP_HEADER, Uuid::new_v4().to_hyphenated().to_string());
-        let response = self.set_default_headers(request).multipart(form).send().await;
+    /// Submits `request` with a multipart form, which `make_form` builds with the part of
+    /// `source`, feeding the uploaded source into `hasher` if given. Streamed forms can't be
+    /// sent twice, so a 429 response is retried with a form built again from a copy of the
+    /// source, which fails with `ClientError::RateLimited` for readers.
+    async fn send_multipart<F>(
+        &self,
+        request: RequestBuilder,
+        source: ModelSource,
+        filename: &str,
+        hasher: Option<&Arc<Mutex<InputHasher>>>,
+        make_form: F,
+    ) -> Result<Response, ClientError>
+    where
+        F: Fn(Part) -> Form,
+    {
+        let initial_hasher = hasher.map(|hasher| hasher.lock().unwrap().clone());
+        let mut source = source;
+        let mut attempt = 1;
+        loop {
+            let retry = source.try_clone();
+            let model_image = match hasher {
+                Some(hasher) => {
+                    let hasher = hasher.clone();
+                    let inspect = move |chunk: &[u8]| hasher.lock().unwrap().update(chunk);
+                    source.into_inspected_part(filename.to_string(), inspect).await?
+                }
+                None => source.into_part(filename.to_string()).await?,
+            };
+            let req = request.try_clone().expect("fail to clone a request without a body");
+            ratelimit::acquire(&self.submit_limiter).await;
+            match self.send(self.set_default_headers(req).multipart(make_form(model_image))).await {
+                Err(ClientError::RateLimited(message, retry_after)) => {
+                    match (retry, ratelimit::retry_wait(retry_after)) {
+                        (Some(retry), Some(wait)) if attempt < MAX_RATE_LIMITED_ATTEMPTS => {
+                            warn!(attempt, wait = ?wait, "rate limited by the server");
+                            self.record_metric(Metric::Retry { operation: "rate_limited" });
+                            tokio::time::sleep(wait).await;
+                            // the source is hashed again from the start
+                            if let (Some(hasher), Some(initial)) = (hasher, &initial_hasher) {
+                                *hasher.lock().unwrap() = initial.clone();
+                            }
+                            source = retry;
+                            attempt += 1;
+                        }
+                        _ => return Err(ClientError::RateLimited(message, retry_after)),
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Sends a DSS request with a form which `make_form` builds with the part of `source`, and
+    /// returns the response if it succeeds
+    async fn send_dss<F>(
+        &self,
+        api: &str,
+        operation: &'static str,
+        source: ModelSource,
+        filename: &str,
+        make_form: F,
+    ) -> Result<Response, ClientError>
+    where
+        F: Fn(Part) -> Form,
+    {
+        let request_id = Uuid::new_v4().to_hyphenated().to_string();
+        Span::current().record("request_id", request_id.as_str());
+        let request =
+            self.client.post(self.api_v1_path(api)).header(REQUEST_ID_HTTP_HEADER, &request_id);
+        let response = self.send_multipart(request, source, filename, None, make_form).await;
 
         match response {
             Ok(res) => {
                 if res.status().is_success() {
-                    match res.bytes().await {
-                        Ok(bytes) => Ok(bytes.to_vec().into_boxed_slice()),
-                        Err(e) => Err(ApiError(format!("fail to fetch the quantized onnx: {}", e))),
+                    if let Some(bytes) = res.content_length() {
+                        self.record_metric(Metric::BytesDownloaded { operation, bytes });
                     }
+                    Ok(res)
                 } else {
+                    let status = res.status();
                     let response: ApiResponse = match res.json().await {
                         Ok(api_response) => api_response,
                         Err(e) => return Err(ApiError(format!("fail to get API response: {}", e))),
                     };
-                    Err(ApiError(format!("fail to compile: {}", &response.message)))
+                    trace_api_error(api, &response);
+                    let msg = format!("fail to compile: {}", &response.message);
+                    if status.is_server_error() {
+                        Err(ClientError::ServerError(status.as_u16(), msg))
+                    } else {
+                        Err(ApiError(msg))
+                    }
                 }
             }
-            Err(e) => Err(ApiError(format!("{}", e))),
+            Err(e) => Err(e),
         }
     }
 }
 
+async fn read_dss_response(response: Response, artifact: &str) -> Result<Box<[u8]>, ClientError> {
+    match response.bytes().await {
+        Ok(bytes) => Ok(bytes.to_vec().into_boxed_slice()),
+        Err(e) => Err(ClientError::Transport(format!("fail to fetch the {}: {}", artifact, e))),
+    }
+}
+
 async fn make_response<F, T>(
     path: &str,
-    response: Result<Response, reqwest::Error>,
+    response: Result<Response, ClientError>,
     f: F,
 ) -> Result<T, ClientError>
 where
@@ -444,20 +1404,63 @@ where
             if response.status().is_success() {
                 match response.bytes().await {
                     Ok(bytes) => f(bytes),
-                    Err(e) => Err(ApiError(format!("fail to deserialize the bytes: {}", e))),
+                    Err(e) => Err(ClientError::Transport(format!("fail to read the body: {}", e))),
                 }
             } else {
-                let err_response: ApiResponse = match response.json().await {
-                    Ok(api_response) => api_response,
-                    Err(e) => {
-                        let msg =
-                            format!("fail to deserialize the error response from {}: {}", path, e);
-                        return Err(ApiError(msg));
-                    }
-                };
-                Err(ApiError(format!("fail to call API {}: {}", path, &err_response.message)))
+                Err(make_error_response(path, response).await)
             }
         }
-        Err(e) => Err(ApiError(format!("{}", e))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Fails with `ClientError::Unsupported` if the server has no artifact of `target_ir` other
+/// than ENF, since only the name of the ENF artifact is confirmed by the compiler API
+pub(crate) fn check_artifact_status(
+    target_ir: TargetIr,
+    status: StatusCode,
+) -> Result<(), ClientError> {
+    if status == StatusCode::NOT_FOUND && target_ir != TargetIr::Enf {
+        return Err(ClientError::Unsupported(format!(
+            "the server has no artifact '{}' of {}, whose name is unconfirmed for IRs other than enf",
+            target_ir.artifact_name(),
+            target_ir
+        )));
+    }
+    Ok(())
+}
+
+/// Returns `ClientError::ServerError` for 5xx statuses and `ClientError::ApiError` otherwise
+async fn make_error_response(path: &str, response: Response) -> ClientError {
+    let status = response.status();
+    let error = |msg: String| {
+        if status.is_server_error() {
+            ClientError::ServerError(status.as_u16(), msg)
+        } else {
+            ApiError(msg)
+        }
+    };
+    let err_response: ApiResponse = match response.json().await {
+        Ok(api_response) => api_response,
+        Err(e) => {
+            let msg = format!("fail to deserialize the error response from {}: {}", path, e);
+            return error(msg);
+        }
+    };
+    trace_api_error(path, &err_response);
+    error(format!("fail to call API {}: {}", path, &err_response.message))
+}
+
+/// Records the trace ID of the server in the current span to connect it with the client traces
+fn trace_api_error(path: &str, response: &ApiResponse) {
+    if let Some(trace_id) = &response.trace_id {
+        Span::current().record("trace_id", trace_id.as_str());
     }
+    warn!(
+        path,
+        error_code = response.error_code.as_str(),
+        trace_id = ?response.trace_id,
+        message = response.message.as_str(),
+        "API error"
+    );
 }
@@ -0,0 +1,50 @@
+//! Client-side metrics
+//!
+//! Every operation of `FuriosaClient` reports its latency, transferred bytes, errors and
+//! retries to the `MetricsSink` set by `FuriosaClient::metrics_sink`. Compile tasks also
+//! report how long they waited in the queue and ran on the server. A sink can forward them
+//! to any metrics backend (e.g., Prometheus or StatsD).
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::CompileTask;
+
+#[derive(Debug, Clone)]
+pub enum Metric {
+    /// Elapsed time of an operation (e.g., 'compile', 'quantize', 'get_task')
+    Latency { operation: &'static str, elapsed: Duration, success: bool },
+    /// Time from the submission of a compile task to its start
+    QueueTime(Duration),
+    /// Time from the start of a compile task to its completion
+    RunTime(Duration),
+    /// Size of an uploaded model
+    BytesUploaded { operation: &'static str, bytes: u64 },
+    /// Size of a downloaded artifact
+    BytesDownloaded { operation: &'static str, bytes: u64 },
+    /// A failed operation with `ClientError::code`
+    Error { operation: &'static str, code: &'static str },
+    /// A retried request (e.g., resuming an interrupted download)
+    Retry { operation: &'static str },
+}
+
+pub trait MetricsSink: Send + Sync {
+    fn record(&self, metric: &Metric);
+}
+
+impl<M: MetricsSink + ?Sized> MetricsSink for Arc<M> {
+    fn record(&self, metric: &Metric) {
+        (**self).record(metric)
+    }
+}
+
+/// Returns the queue time and the run time of a completed task
+pub(crate) fn task_times(task: &CompileTask) -> Option<(Duration, Duration)> {
+    let seconds = |from: i64, to: i64| Duration::from_secs((to - from).max(0) as u64);
+    match (task.start_time, task.finish_time) {
+        (Some(start), Some(finish)) => {
+            Some((seconds(task.submit_time, start), seconds(start, finish)))
+        }
+        _ => None,
+    }
+}
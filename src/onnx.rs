@@ -0,0 +1,385 @@
+//! A minimal reader of ONNX models
+//!
+//! It only decodes the parts of `ModelProto` which the client needs (graph inputs, outputs,
+//! initializers, nodes and their attributes). Other fields are skipped without being decoded.
+//! `read_input_tensors` reads a model from a seekable reader with bounded memory, seeking
+//! over nodes and tensor data, so multi-GB models don't need to be loaded.
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use serde_json::Value as Json;
+
+use crate::ClientError;
+
+// Field numbers from https://github.com/onnx/onnx/blob/master/onnx/onnx.proto
+const MODEL_GRAPH_FIELD: u64 = 7;
+const GRAPH_NODE_FIELD: u64 = 1;
+const GRAPH_NAME_FIELD: u64 = 2;
+const GRAPH_INITIALIZER_FIELD: u64 = 5;
+const GRAPH_INPUT_FIELD: u64 = 11;
+const GRAPH_OUTPUT_FIELD: u64 = 12;
+const NODE_INPUT_FIELD: u64 = 1;
+const NODE_OUTPUT_FIELD: u64 = 2;
+const NODE_NAME_FIELD: u64 = 3;
+const NODE_OP_TYPE_FIELD: u64 = 4;
+const NODE_ATTRIBUTE_FIELD: u64 = 5;
+const NODE_DOMAIN_FIELD: u64 = 7;
+const ATTRIBUTE_NAME_FIELD: u64 = 1;
+const ATTRIBUTE_F_FIELD: u64 = 2;
+const ATTRIBUTE_I_FIELD: u64 = 3;
+const ATTRIBUTE_S_FIELD: u64 = 4;
+const ATTRIBUTE_FLOATS_FIELD: u64 = 7;
+const ATTRIBUTE_INTS_FIELD: u64 = 8;
+const ATTRIBUTE_STRINGS_FIELD: u64 = 9;
+const VALUE_INFO_NAME_FIELD: u64 = 1;
+const TENSOR_NAME_FIELD: u64 = 8;
+
+/// Maximum size of a graph input or a tensor name which `read_input_tensors` reads in memory
+const MAX_STREAMED_MESSAGE_LEN: u64 = 4 << 20;
+
+#[derive(Debug, Clone, Default)]
+pub struct OnnxGraph {
+    pub name: String,
+    pub nodes: Vec<OnnxNode>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub initializers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OnnxNode {
+    pub name: String,
+    pub op_type: String,
+    pub domain: String,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// Attributes of which values are scalars, strings or lists of them
+    pub attributes: Vec<(String, Json)>,
+}
+
+impl OnnxGraph {
+    /// Returns the graph inputs which are not initialized by constant tensors
+    pub fn input_tensors(&self) -> Vec<String> {
+        self.inputs.iter().filter(|name| !self.initializers.contains(name)).cloned().collect()
+    }
+}
+
+pub fn parse_graph(bytes: &[u8]) -> Result<OnnxGraph, ClientError> {
+    let mut graph = None;
+    for field in Fields::new(bytes) {
+        if let (MODEL_GRAPH_FIELD, Value::Bytes(buf)) = field? {
+            graph = Some(decode_graph(buf)?);
+        }
+    }
+    graph.ok_or_else(|| ClientError::InvalidModel("no graph found in the ONNX model".to_string()))
+}
+
+/// Returns the graph inputs which are not initialized by constant tensors, reading `len` bytes
+/// of a model from `reader`. Only the graph inputs and the names of initializers are read in
+/// memory, and the other fields are skipped by seeking.
+pub fn read_input_tensors<R: Read + Seek>(reader: R, len: u64) -> Result<Vec<String>, ClientError> {
+    let mut fields = FieldReader { reader, pos: 0, end: len };
+    let mut graph = OnnxGraph::default();
+    let mut found = false;
+    while let Some((num, len)) = fields.next_field()? {
+        if num != MODEL_GRAPH_FIELD || len.is_none() {
+            fields.skip_value(len)?;
+            continue;
+        }
+        found = true;
+        let outer_end = fields.enter(len.unwrap_or_default());
+        while let Some((num, len)) = fields.next_field()? {
+            match (num, len) {
+                (GRAPH_INPUT_FIELD, Some(len)) => {
+                    let buf = fields.read(len)?;
+                    graph.inputs.push(decode_name(&buf, VALUE_INFO_NAME_FIELD)?);
+                }
+                (GRAPH_INITIALIZER_FIELD, Some(len)) => {
+                    let tensor_end = fields.enter(len);
+                    let mut name = String::new();
+                    while let Some((num, len)) = fields.next_field()? {
+                        match (num, len) {
+                            (TENSOR_NAME_FIELD, Some(len)) => {
+                                name = decode_string(&fields.read(len)?)?
+                            }
+                            _ => fields.skip_value(len)?,
+                        }
+                    }
+                    fields.leave(tensor_end);
+                    graph.initializers.push(name);
+                }
+                _ => fields.skip_value(len)?,
+            }
+        }
+        fields.leave(outer_end);
+    }
+    if !found {
+        return Err(ClientError::InvalidModel("no graph found in the ONNX model".to_string()));
+    }
+    Ok(graph.input_tensors())
+}
+
+fn decode_graph(bytes: &[u8]) -> Result<OnnxGraph, ClientError> {
+    let mut graph = OnnxGraph::default();
+    for field in Fields::new(bytes) {
+        match field? {
+            (GRAPH_NODE_FIELD, Value::Bytes(buf)) => graph.nodes.push(decode_node(buf)?),
+            (GRAPH_NAME_FIELD, Value::Bytes(buf)) => graph.name = decode_string(buf)?,
+            (GRAPH_INITIALIZER_FIELD, Value::Bytes(buf)) => {
+                graph.initializers.push(decode_name(buf, TENSOR_NAME_FIELD)?)
+            }
+            (GRAPH_INPUT_FIELD, Value::Bytes(buf)) => {
+                graph.inputs.push(decode_name(buf, VALUE_INFO_NAME_FIELD)?)
+            }
+            (GRAPH_OUTPUT_FIELD, Value::Bytes(buf)) => {
+                graph.outputs.push(decode_name(buf, VALUE_INFO_NAME_FIELD)?)
+            }
+            _ => {}
+        }
+    }
+    Ok(graph)
+}
+
+fn decode_node(bytes: &[u8]) -> Result<OnnxNode, ClientError> {
+    let mut node = OnnxNode::default();
+    for field in Fields::new(bytes) {
+        match field? {
+            (NODE_INPUT_FIELD, Value::Bytes(buf)) => node.inputs.push(decode_string(buf)?),
+            (NODE_OUTPUT_FIELD, Value::Bytes(buf)) => node.outputs.push(decode_string(buf)?),
+            (NODE_NAME_FIELD, Value::Bytes(buf)) => node.name = decode_string(buf)?,
+            (NODE_OP_TYPE_FIELD, Value::Bytes(buf)) => node.op_type = decode_string(buf)?,
+            (NODE_ATTRIBUTE_FIELD, Value::Bytes(buf)) => {
+                node.attributes.push(decode_attribute(buf)?)
+            }
+            (NODE_DOMAIN_FIELD, Value::Bytes(buf)) => node.domain = decode_string(buf)?,
+            _ => {}
+        }
+    }
+    Ok(node)
+}
+
+fn decode_attribute(bytes: &[u8]) -> Result<(String, Json), ClientError> {
+    let mut name = String::new();
+    let mut value = Json::Null;
+    let mut list = Vec::new();
+    for field in Fields::new(bytes) {
+        match field? {
+            (ATTRIBUTE_NAME_FIELD, Value::Bytes(buf)) => name = decode_string(buf)?,
+            (ATTRIBUTE_F_FIELD, Value::Fixed32(bits)) => value = Json::from(f32::from_bits(bits)),
+            (ATTRIBUTE_I_FIELD, Value::Varint(v)) => value = Json::from(v as i64),
+            (ATTRIBUTE_S_FIELD, Value::Bytes(buf)) => value = Json::from(decode_string(buf)?),
+            (ATTRIBUTE_FLOATS_FIELD, Value::Fixed32(bits)) => {
+                list.push(Json::from(f32::from_bits(bits)))
+            }
+            (ATTRIBUTE_FLOATS_FIELD, Value::Bytes(buf)) => {
+                for chunk in buf.chunks_exact(4) {
+                    let bits = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    list.push(Json::from(f32::from_bits(bits)));
+                }
+            }
+            (ATTRIBUTE_INTS_FIELD, Value::Varint(v)) => list.push(Json::from(v as i64)),
+            (ATTRIBUTE_INTS_FIELD, Value::Bytes(buf)) => {
+                let mut packed = Fields::new(buf);
+                while !packed.buf.is_empty() {
+                    list.push(Json::from(packed.varint()? as i64));
+                }
+            }
+            (ATTRIBUTE_STRINGS_FIELD, Value::Bytes(buf)) => {
+                list.push(Json::from(decode_string(buf)?))
+            }
+            _ => {}
+        }
+    }
+    if value.is_null() && !list.is_empty() {
+        value = Json::Array(list);
+    }
+    Ok((name, value))
+}
+
+fn decode_name(bytes: &[u8], name_field: u64) -> Result<String, ClientError> {
+    for field in Fields::new(bytes) {
+        if let (num, Value::Bytes(buf)) = field? {
+            if num == name_field {
+                return decode_string(buf);
+            }
+        }
+    }
+    Ok(String::new())
+}
+
+fn decode_string(bytes: &[u8]) -> Result<String, ClientError> {
+    String::from_utf8(bytes.to_vec())
+        .map_err(|e| ClientError::InvalidModel(format!("invalid UTF-8 string: {}", e)))
+}
+
+enum Value<'a> {
+    Varint(u64),
+    Fixed32(u32),
+    Fixed64,
+    Bytes(&'a [u8]),
+}
+
+/// Iterator over the (field number, value) pairs of a protobuf message
+struct Fields<'a> {
+    buf: &'a [u8],
+    failed: bool,
+}
+
+impl<'a> Fields<'a> {
+    fn new(buf: &'a [u8]) -> Fields<'a> {
+        Fields { buf, failed: false }
+    }
+
+    fn varint(&mut self) -> Result<u64, ClientError> {
+        let mut value = 0u64;
+        for (i, byte) in self.buf.iter().enumerate().take(10) {
+            value |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                self.buf = &self.buf[i + 1..];
+                return Ok(value);
+            }
+        }
+        Err(truncated())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ClientError> {
+        if self.buf.len() < len {
+            return Err(truncated());
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn field(&mut self) -> Result<(u64, Value<'a>), ClientError> {
+        let key = self.varint()?;
+        let value = match key & 0x7 {
+            0 => Value::Varint(self.varint()?),
+            1 => self.take(8).map(|_| Value::Fixed64)?,
+            2 => {
+                let len = usize::try_from(self.varint()?).map_err(|_| truncated())?;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                let buf = self.take(4)?;
+                Value::Fixed32(u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]))
+            }
+            wire_type => {
+                let msg = format!("unsupported protobuf wire type {}", wire_type);
+                return Err(ClientError::InvalidModel(msg));
+            }
+        };
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = Result<(u64, Value<'a>), ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.buf.is_empty() {
+            return None;
+        }
+        let field = self.field();
+        self.failed = field.is_err();
+        Some(field)
+    }
+}
+
+/// Reads protobuf fields from a seekable reader, which are in `pos..end`
+struct FieldReader<R> {
+    reader: R,
+    pos: u64,
+    end: u64,
+}
+
+impl<R: Read + Seek> FieldReader<R> {
+    /// Returns the field number and the length of a length-delimited value, whose bytes follow.
+    /// Other values are skipped.
+    fn next_field(&mut self) -> Result<Option<(u64, Option<u64>)>, ClientError> {
+        if self.pos >= self.end {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let len = match key & 0x7 {
+            0 => self.varint().map(|_| None)?,
+            1 => self.skip(8).map(|_| None)?,
+            2 => Some(self.varint()?),
+            5 => self.skip(4).map(|_| None)?,
+            wire_type => {
+                let msg = format!("unsupported protobuf wire type {}", wire_type);
+                return Err(ClientError::InvalidModel(msg));
+            }
+        };
+        Ok(Some((key >> 3, len)))
+    }
+
+    fn skip_value(&mut self, len: Option<u64>) -> Result<(), ClientError> {
+        match len {
+            Some(len) => self.skip(len),
+            None => Ok(()),
+        }
+    }
+
+    /// Limits the fields to a nested message of `len` bytes until `leave` is called with
+    /// the returned end of the outer message
+    fn enter(&mut self, len: u64) -> u64 {
+        let outer_end = self.end;
+        self.end = self.pos.saturating_add(len).min(outer_end);
+        outer_end
+    }
+
+    fn leave(&mut self, outer_end: u64) {
+        self.end = outer_end;
+    }
+
+    fn varint(&mut self) -> Result<u64, ClientError> {
+        let mut value = 0u64;
+        for i in 0..10 {
+            let mut byte = [0u8];
+            self.read_exact(&mut byte)?;
+            value |= u64::from(byte[0] & 0x7f) << (7 * i);
+            if byte[0] & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(truncated())
+    }
+
+    fn skip(&mut self, len: u64) -> Result<(), ClientError> {
+        let offset = i64::try_from(len).map_err(|_| truncated())?;
+        if self.end - self.pos < len {
+            return Err(truncated());
+        }
+        self.reader.seek(SeekFrom::Current(offset))?;
+        self.pos += len;
+        Ok(())
+    }
+
+    fn read(&mut self, len: u64) -> Result<Vec<u8>, ClientError> {
+        if len > MAX_STREAMED_MESSAGE_LEN {
+            let msg = format!("a graph input or a tensor name of {} bytes is too large", len);
+            return Err(ClientError::InvalidModel(msg));
+        }
+        let mut buf = vec![0u8; len as usize];
+        self.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ClientError> {
+        if self.end - self.pos < buf.len() as u64 {
+            return Err(truncated());
+        }
+        self.reader.read_exact(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => truncated(),
+            _ => e.into(),
+        })?;
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+}
+
+fn truncated() -> ClientError {
+    ClientError::InvalidModel("truncated protobuf message".to_string())
+}
@@ -0,0 +1,201 @@
+//! Checks operators of a model against the NPU before submitting a compile task
+//!
+//! The support table is bundled from `configs/operators.yml`, so the check runs locally
+//! without calling the API. The table only has operators verified to compile, each with its
+//! source, so operators outside it are reported as unknown instead of unsupported.
+
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{onnx, tflite, ClientError};
+
+lazy_static! {
+    static ref SUPPORT_TABLE: SupportTable =
+        serde_yaml::from_str(include_str!("../configs/operators.yml"))
+            .expect("invalid operator support table");
+}
+
+/// ONNX operators in these domains are looked up in the support table
+static ONNX_DEFAULT_DOMAINS: &[&str] = &["", "ai.onnx"];
+
+#[derive(Deserialize)]
+struct SupportTable {
+    presets: Vec<Preset>,
+}
+
+#[derive(Deserialize)]
+struct Preset {
+    name: String,
+    /// File name of the bundled NPU spec in `configs`
+    spec: String,
+    onnx: HashMap<String, OperatorSupport>,
+    tflite: HashMap<String, OperatorSupport>,
+}
+
+#[derive(Deserialize)]
+struct OperatorSupport {
+    /// Where the entry is verified, which is required for every entry
+    source: String,
+    #[serde(default)]
+    device: Option<Device>,
+    #[serde(default)]
+    attributes: HashMap<String, Vec<Value>>,
+}
+
+/// Returns the bundled NPU spec of a preset
+fn bundled_spec(file: &str) -> Option<Value> {
+    let yaml = match file {
+        "64dpes.yml" => include_str!("../configs/64dpes.yml"),
+        _ => return None,
+    };
+    Some(serde_yaml::from_str(yaml).expect("invalid bundled NPU spec"))
+}
+
+#[derive(Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Device {
+    Npu,
+    Cpu,
+}
+
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelFormat {
+    Onnx,
+    Tflite,
+}
+
+impl ModelFormat {
+    /// Detects the format from the model binary. TFLite models are identified by their
+    /// flatbuffer file identifier and the others are assumed to be ONNX.
+    pub fn detect(source: &[u8]) -> ModelFormat {
+        if tflite::is_tflite(source) {
+            ModelFormat::Tflite
+        } else {
+            ModelFormat::Onnx
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct OperatorIssue {
+    /// Node name in ONNX or the first output tensor name in TFLite
+    pub node: String,
+    pub op_type: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct OperatorReport {
+    /// Name of the support table preset matched with the target NPU spec
+    pub npu_preset: String,
+    pub format: ModelFormat,
+    pub num_operators: usize,
+    /// Operators which aren't verified for the NPU, which may or may not compile
+    pub unknown_operators: Vec<OperatorIssue>,
+    pub unsupported_attributes: Vec<OperatorIssue>,
+    pub cpu_fallback_operators: Vec<OperatorIssue>,
+}
+
+impl OperatorReport {
+    /// Returns true if all operators are verified to compile with their attributes. It doesn't
+    /// mean that the model is compilable, nor does false mean that it isn't.
+    pub fn is_verified(&self) -> bool {
+        self.unknown_operators.is_empty() && self.unsupported_attributes.is_empty()
+    }
+
+    fn add(
+        &mut self,
+        node: &str,
+        op_type: &str,
+        support: Option<&OperatorSupport>,
+        attributes: &[(String, Value)],
+    ) {
+        self.num_operators += 1;
+        let issue = |message: String| OperatorIssue {
+            node: node.to_string(),
+            op_type: op_type.to_string(),
+            message,
+        };
+
+        let support = match support {
+            Some(support) => support,
+            None => {
+                let msg = format!("operator '{}' is not verified for the NPU", op_type);
+                self.unknown_operators.push(issue(msg));
+                return;
+            }
+        };
+
+        let mut attribute_issues = Vec::new();
+        for (name, value) in attributes {
+            if let Some(allowed) = support.attributes.get(name) {
+                if !allowed.contains(value) {
+                    let msg = format!(
+                        "attribute '{}' = {} is not supported (source: {})",
+                        name, value, support.source
+                    );
+                    attribute_issues.push(issue(msg));
+                }
+            }
+        }
+
+        if !attribute_issues.is_empty() {
+            self.unsupported_attributes.extend(attribute_issues);
+        } else if support.device == Some(Device::Cpu) {
+            let msg = format!(
+                "operator '{}' will fall back to CPU (source: {})",
+                op_type, support.source
+            );
+            self.cpu_fallback_operators.push(issue(msg));
+        }
+    }
+}
+
+/// Walks the operators of an ONNX or TFLite model and reports ones which aren't verified for
+/// the target NPU, have unsupported attributes or run on CPU. It fails with
+/// `ClientError::UnsupportedNpuSpec` unless the spec equals a bundled one.
+pub fn check_operators(
+    target_npu_spec: &Value,
+    source: &[u8],
+) -> Result<OperatorReport, ClientError> {
+    let preset = SUPPORT_TABLE
+        .presets
+        .iter()
+        .find(|preset| bundled_spec(&preset.spec).as_ref() == Some(target_npu_spec))
+        .ok_or_else(|| {
+            ClientError::UnsupportedNpuSpec("no operator support table for the spec".to_string())
+        })?;
+
+    let format = ModelFormat::detect(source);
+    let mut report = OperatorReport {
+        npu_preset: preset.name.clone(),
+        format,
+        num_operators: 0,
+        unknown_operators: Vec::new(),
+        unsupported_attributes: Vec::new(),
+        cpu_fallback_operators: Vec::new(),
+    };
+
+    match format {
+        ModelFormat::Onnx => {
+            for node in onnx::parse_graph(source)?.nodes {
+                let support = if ONNX_DEFAULT_DOMAINS.contains(&node.domain.as_str()) {
+                    preset.onnx.get(&node.op_type)
+                } else {
+                    None
+                };
+                report.add(&node.name, &node.op_type, support, &node.attributes);
+            }
+        }
+        ModelFormat::Tflite => {
+            for op in tflite::parse_operators(source)? {
+                report.add(&op.name, &op.op_type, preset.tflite.get(&op.op_type), &[]);
+            }
+        }
+    }
+    Ok(report)
+}
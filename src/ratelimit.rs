@@ -0,0 +1,144 @@
+//! Client-side rate limiting and quota awareness
+//!
+//! Submissions (compile tasks and DSS calls) and polls of compile tasks can be throttled by
+//! token buckets, so that services sharing an API key stay under the server-side limits.
+//! Responses with 429 are retried after `Retry-After`, building multipart uploads again from
+//! their sources, and fail with `ClientError::RateLimited` if the request can't be sent again
+//! (e.g., uploads from readers) or `Retry-After` exceeds `MAX_RETRY_AFTER`. The latest quota
+//! headers sent by the server are kept as `QuotaInfo`.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use serde::Serialize;
+
+use crate::ClientError;
+
+static RATE_LIMIT_LIMIT_HEADER: &str = "X-RateLimit-Limit";
+static RATE_LIMIT_REMAINING_HEADER: &str = "X-RateLimit-Remaining";
+static RATE_LIMIT_RESET_HEADER: &str = "X-RateLimit-Reset";
+static QUOTA_LIMIT_HEADER: &str = "X-FuriosaAI-Quota-Limit";
+static QUOTA_REMAINING_HEADER: &str = "X-FuriosaAI-Quota-Remaining";
+/// Longest `Retry-After` which the client waits for before retrying a request
+pub(crate) const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+/// Wait before retrying a 429 response without `Retry-After`
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+/// Lowest rate, a request per day, which bounds the wait for a token
+const MIN_REQUESTS_PER_SECOND: f64 = 1.0 / (24.0 * 60.0 * 60.0);
+
+/// Allows `requests_per_second` on average and bursts up to `burst` requests
+#[derive(Copy, Clone, Debug)]
+pub struct RateLimit {
+    requests_per_second: f64,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Fails if the rate isn't a finite number of at least a request per day
+    pub fn per_second(requests_per_second: f64) -> Result<RateLimit, ClientError> {
+        // rejects NaN too, which isn't greater than the minimum
+        if !(requests_per_second >= MIN_REQUESTS_PER_SECOND && requests_per_second.is_finite()) {
+            let msg = format!("invalid rate limit: {} requests per second", requests_per_second);
+            return Err(ClientError::io_error(std::io::ErrorKind::InvalidInput, &msg));
+        }
+        Ok(RateLimit { requests_per_second, burst: 1 })
+    }
+
+    /// Fails if the rate isn't a finite number of at least a request per day
+    pub fn per_minute(requests_per_minute: f64) -> Result<RateLimit, ClientError> {
+        RateLimit::per_second(requests_per_minute / 60.0)
+    }
+
+    pub fn burst(mut self, burst: u32) -> RateLimit {
+        self.burst = burst.max(1);
+        self
+    }
+}
+
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    /// Available tokens and the time when they were counted
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limit: RateLimit) -> TokenBucket {
+        TokenBucket { limit, state: Mutex::new((limit.burst as f64, Instant::now())) }
+    }
+
+    /// Waits until a token is available and takes it
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let refilled =
+                    now.duration_since(state.1).as_secs_f64() * self.limit.requests_per_second;
+                *state = ((state.0 + refilled).min(self.limit.burst as f64), now);
+                if state.0 >= 1.0 {
+                    state.0 -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - state.0) / self.limit.requests_per_second)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Takes a token from the limiter if it is set
+pub(crate) async fn acquire(limiter: &Option<TokenBucket>) {
+    if let Some(limiter) = limiter {
+        limiter.acquire().await;
+    }
+}
+
+/// Rate limit and quota of the API key reported by the latest response
+#[derive(Serialize, Debug, Clone, Default, Eq, PartialEq)]
+pub struct QuotaInfo {
+    /// Requests allowed in the current window
+    pub rate_limit: Option<u64>,
+    pub rate_remaining: Option<u64>,
+    /// Seconds until the current window resets
+    pub rate_reset: Option<u64>,
+    /// Usage allowed to the API key (e.g., compilations per month)
+    pub quota_limit: Option<u64>,
+    pub quota_remaining: Option<u64>,
+}
+
+impl QuotaInfo {
+    /// Returns `None` if the headers have no rate limit and quota
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Option<QuotaInfo> {
+        let get = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<u64>().ok();
+        let quota = QuotaInfo {
+            rate_limit: get(RATE_LIMIT_LIMIT_HEADER),
+            rate_remaining: get(RATE_LIMIT_REMAINING_HEADER),
+            rate_reset: get(RATE_LIMIT_RESET_HEADER),
+            quota_limit: get(QUOTA_LIMIT_HEADER),
+            quota_remaining: get(QUOTA_REMAINING_HEADER),
+        };
+        if quota == QuotaInfo::default() {
+            None
+        } else {
+            Some(quota)
+        }
+    }
+}
+
+/// Returns how long to wait before retrying a 429 response, or `None` if `Retry-After` is
+/// longer than `MAX_RETRY_AFTER`
+pub(crate) fn retry_wait(retry_after: Option<Duration>) -> Option<Duration> {
+    let wait = retry_after.unwrap_or(DEFAULT_RETRY_AFTER);
+    if wait <= MAX_RETRY_AFTER {
+        Some(wait)
+    } else {
+        None
+    }
+}
+
+/// Parses `Retry-After` in seconds. HTTP dates are not supported.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers.get(RETRY_AFTER)?.to_str().ok()?.trim().parse::<u64>().ok()?;
+    Some(Duration::from_secs(seconds))
+}
@@ -0,0 +1,168 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use futures_util::StreamExt;
+use reqwest::multipart::Part;
+use reqwest::Body;
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+
+use crate::{onnx, ClientError, APPLICATION_OCTET_STREAM_MIME};
+
+/// A model to be uploaded
+///
+/// Files and readers are streamed in the multipart body, so large models don't need to be
+/// loaded in memory.
+pub enum ModelSource {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+    Reader { reader: Box<dyn AsyncRead + Send + Sync + Unpin>, length: u64 },
+}
+
+impl ModelSource {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> ModelSource {
+        ModelSource::File(path.as_ref().to_path_buf())
+    }
+
+    /// `length` must be the exact number of bytes which `reader` yields
+    pub fn from_reader<R>(reader: R, length: u64) -> ModelSource
+    where
+        R: AsyncRead + Send + Sync + Unpin + 'static,
+    {
+        ModelSource::Reader { reader: Box::new(reader), length }
+    }
+
+    /// Returns the size of the model in bytes if it's known without reading the model
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            ModelSource::Bytes(bytes) => Some(bytes.len() as u64),
+            ModelSource::File(path) => std::fs::metadata(path).ok().map(|m| m.len()),
+            ModelSource::Reader { length, .. } => Some(*length),
+        }
+    }
+
+    /// Returns the model in memory, reading the file if the source is a file
+    pub fn to_bytes(&self) -> Result<Cow<'_, [u8]>, ClientError> {
+        match self {
+            ModelSource::Bytes(bytes) => Ok(Cow::Borrowed(bytes)),
+            ModelSource::File(path) => Ok(Cow::Owned(std::fs::read(path)?)),
+            ModelSource::Reader { .. } => Err(ClientError::io_error(
+                std::io::ErrorKind::InvalidInput,
+                "a reader source cannot be read more than once",
+            )),
+        }
+    }
+
+    /// Returns the graph inputs of an ONNX model which are not initializers. Files are read
+    /// with bounded memory, skipping tensor data.
+    pub(crate) fn input_tensors(&self) -> Result<Vec<String>, ClientError> {
+        match self {
+            ModelSource::Bytes(bytes) => {
+                onnx::read_input_tensors(std::io::Cursor::new(bytes), bytes.len() as u64)
+            }
+            ModelSource::File(path) => {
+                let file = std::fs::File::open(path)?;
+                let len = file.metadata()?.len();
+                onnx::read_input_tensors(std::io::BufReader::new(file), len)
+            }
+            ModelSource::Reader { .. } => Err(ClientError::io_error(
+                std::io::ErrorKind::InvalidInput,
+                "the graph of a reader source cannot be read before uploading",
+            )),
+        }
+    }
+
+    /// Returns a copy of the source, or `None` for readers which can be read only once
+    pub fn try_clone(&self) -> Option<ModelSource> {
+        match self {
+            ModelSource::Bytes(bytes) => Some(ModelSource::Bytes(bytes.clone())),
+            ModelSource::File(path) => Some(ModelSource::File(path.clone())),
+            ModelSource::Reader { .. } => None,
+        }
+    }
+
+    pub(crate) async fn into_part(self, filename: String) -> Result<Part, ClientError> {
+        self.into_inspected_part(filename, |_| ()).await
+    }
+
+    /// Creates a part which calls `inspect` with each chunk of the model while it's uploaded
+    pub(crate) async fn into_inspected_part<F>(
+        self,
+        filename: String,
+        mut inspect: F,
+    ) -> Result<Part, ClientError>
+    where
+        F: FnMut(&[u8]) + Send + Sync + 'static,
+    {
+        let part = match self {
+            ModelSource::Bytes(bytes) => {
+                inspect(&bytes);
+                Part::bytes(bytes)
+            }
+            ModelSource::File(path) => {
+                let file = tokio::fs::File::open(&path).await?;
+                let length = file.metadata().await?.len();
+                let stream = ReaderStream::new(file).inspect(move |chunk| {
+                    if let Ok(chunk) = chunk {
+                        inspect(chunk)
+                    }
+                });
+                Part::stream_with_length(Body::wrap_stream(stream), length)
+            }
+            ModelSource::Reader { reader, length } => {
+                let stream = ReaderStream::new(reader).inspect(move |chunk| {
+                    if let Ok(chunk) = chunk {
+                        inspect(chunk)
+                    }
+                });
+                Part::stream_with_length(Body::wrap_stream(stream), length)
+            }
+        };
+        Ok(part
+            .file_name(filename)
+            .mime_str(APPLICATION_OCTET_STREAM_MIME)
+            .expect("Invalid MIME type"))
+    }
+}
+
+impl From<Vec<u8>> for ModelSource {
+    fn from(bytes: Vec<u8>) -> Self {
+        ModelSource::Bytes(bytes)
+    }
+}
+
+impl<'a> From<Cow<'a, [u8]>> for ModelSource {
+    fn from(bytes: Cow<'a, [u8]>) -> Self {
+        ModelSource::Bytes(bytes.into_owned())
+    }
+}
+
+impl From<&[u8]> for ModelSource {
+    fn from(bytes: &[u8]) -> Self {
+        ModelSource::Bytes(bytes.to_vec())
+    }
+}
+
+impl From<&Vec<u8>> for ModelSource {
+    fn from(bytes: &Vec<u8>) -> Self {
+        ModelSource::Bytes(bytes.clone())
+    }
+}
+
+impl fmt::Debug for ModelSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelSource::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            ModelSource::File(path) => write!(f, "File({})", path.display()),
+            ModelSource::Reader { length, .. } => write!(f, "Reader({} bytes)", length),
+        }
+    }
+}
+
+pub(crate) fn file_name<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref()
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| String::from("noname"))
+}
@@ -0,0 +1,30 @@
+//! OpenTelemetry context propagation through HTTP headers
+//!
+//! The context of a `tracing` span is injected into request headers (e.g., `traceparent`) by
+//! the global text map propagator, so that the server continues the trace of the client.
+//! It requires `tracing_opentelemetry::OpenTelemetryLayer` in the subscriber and a propagator
+//! set by `opentelemetry::global::set_text_map_propagator`.
+
+use opentelemetry::propagation::Injector;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl<'a> Injector for HeaderInjector<'a> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) =
+            (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value))
+        {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+pub(crate) fn inject_context(span: &Span, headers: &mut HeaderMap) {
+    let context = span.context();
+    opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers))
+    });
+}
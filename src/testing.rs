@@ -0,0 +1,643 @@
+//! In-process mock of Furiosa API for tests
+//!
+//! `MockServer` serves the compiler task API and the DSS API on a local port, so the whole
+//! `compile` flow can be tested without API keys and network. Each submitted compile task
+//! follows a `CompileScript` which defines its phases, artifact, logs, delay and failures.
+//!
+//! ```no_run
+//! # async fn example() {
+//! use furiosa_client::testing::{CompileScript, MockServer};
+//! use furiosa_client::CompileRequest;
+//!
+//! let server = MockServer::start();
+//! server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+//!
+//! let client = server.client("0.4.0");
+//! let request = CompileRequest::new(serde_json::json!({}), b"model".to_vec());
+//! assert_eq!(&*client.compile(request).await.unwrap(), b"ENF");
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::StreamExt;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use sha2::{Digest, Sha256};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::download::CHECKSUM_HTTP_HEADER;
+use crate::{
+    ApiResponse, Capabilities, CompileTask, CompileTaskPhase, FuriosaClient, TargetIr, TaskFilter,
+    TaskPage, VersionInfo, ACCESS_KEY_ID_HTTP_HEADER, SECRET_ACCESS_KEY_HTTP_HEADER,
+};
+
+static COMPILER_TASKS_PATH: &str = "/api/compiler/v1alpha1/tasks";
+static DSS_PATH: &str = "/api/v1/dss/";
+
+/// A response of the mock server
+#[derive(Clone, Debug)]
+pub enum MockResponse {
+    Bytes(Vec<u8>),
+    /// An error response with an `ApiResponse` JSON payload
+    Error {
+        status: u16,
+        error_code: String,
+        message: String,
+    },
+    /// A 429 response with `Retry-After` in seconds
+    RateLimited {
+        retry_after: u64,
+    },
+}
+
+impl MockResponse {
+    pub fn bytes<B: Into<Vec<u8>>>(bytes: B) -> MockResponse {
+        MockResponse::Bytes(bytes.into())
+    }
+
+    pub fn error(status: u16, error_code: &str, message: &str) -> MockResponse {
+        MockResponse::Error {
+            status,
+            error_code: error_code.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// How a submitted compile task proceeds
+#[derive(Clone, Debug)]
+pub struct CompileScript {
+    phases: Vec<CompileTaskPhase>,
+    artifact: Vec<u8>,
+    ir_artifacts: BTreeMap<TargetIr, Vec<u8>>,
+    missing_irs: BTreeSet<TargetIr>,
+    logs: String,
+    delay: Duration,
+    submit_error: Option<MockResponse>,
+    poll_errors: Vec<MockResponse>,
+    interrupt_download_at: Option<usize>,
+    resume_errors: Vec<MockResponse>,
+}
+
+impl CompileScript {
+    /// The task becomes `Pending`, `Running` and `Succeeded` with `artifact`
+    pub fn succeed<B: Into<Vec<u8>>>(artifact: B) -> CompileScript {
+        CompileScript {
+            phases: vec![
+                CompileTaskPhase::Pending,
+                CompileTaskPhase::Running,
+                CompileTaskPhase::Succeeded,
+            ],
+            artifact: artifact.into(),
+            ir_artifacts: BTreeMap::new(),
+            missing_irs: BTreeSet::new(),
+            logs: String::new(),
+            delay: Duration::from_millis(0),
+            submit_error: None,
+            poll_errors: Vec::new(),
+            interrupt_download_at: None,
+            resume_errors: Vec::new(),
+        }
+    }
+
+    /// The task becomes `Pending`, `Running` and `Failed` with `logs`
+    pub fn fail(logs: &str) -> CompileScript {
+        CompileScript {
+            phases: vec![
+                CompileTaskPhase::Pending,
+                CompileTaskPhase::Running,
+                CompileTaskPhase::Failed,
+            ],
+            logs: logs.to_string(),
+            ..CompileScript::succeed(Vec::new())
+        }
+    }
+
+    /// The submission is rejected with `response`
+    pub fn reject(response: MockResponse) -> CompileScript {
+        CompileScript { submit_error: Some(response), ..CompileScript::succeed(Vec::new()) }
+    }
+
+    /// Sets the phases which the task goes through on each poll. The last one must be
+    /// `Succeeded` or `Failed`.
+    pub fn phases(mut self, phases: Vec<CompileTaskPhase>) -> CompileScript {
+        self.phases = phases;
+        self
+    }
+
+    pub fn logs(mut self, logs: &str) -> CompileScript {
+        self.logs = logs.to_string();
+        self
+    }
+
+    /// Delays every response about the task
+    pub fn delay(mut self, delay: Duration) -> CompileScript {
+        self.delay = delay;
+        self
+    }
+
+    /// Responds to the polls in order with `errors` before the scripted phases
+    pub fn poll_errors(mut self, errors: Vec<MockResponse>) -> CompileScript {
+        self.poll_errors = errors;
+        self
+    }
+
+    /// Serves `artifact` as the artifact of `target_ir` instead of the one given to `succeed`
+    pub fn ir_artifact<B: Into<Vec<u8>>>(
+        mut self,
+        target_ir: TargetIr,
+        artifact: B,
+    ) -> CompileScript {
+        self.ir_artifacts.insert(target_ir, artifact.into());
+        self
+    }
+
+    /// Responds to the downloads of the artifact of `target_ir` with 404
+    pub fn missing_ir_artifact(mut self, target_ir: TargetIr) -> CompileScript {
+        self.missing_irs.insert(target_ir);
+        self
+    }
+
+    /// Drops the connection after sending `bytes` bytes of the artifact in the first download,
+    /// which may be the whole artifact
+    pub fn interrupt_download_at(mut self, bytes: usize) -> CompileScript {
+        self.interrupt_download_at = Some(bytes);
+        self
+    }
+
+    /// Responds to the downloads after the first one in order with `errors`
+    pub fn resume_errors(mut self, errors: Vec<MockResponse>) -> CompileScript {
+        self.resume_errors = errors;
+        self
+    }
+}
+
+/// A request received by the mock server
+#[derive(Clone, Debug)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+}
+
+struct MockTask {
+    task: CompileTask,
+    script: CompileScript,
+    next_phase: usize,
+    downloads: usize,
+}
+
+struct MockState {
+    version: VersionInfo,
+    capabilities: Option<Capabilities>,
+    credential: Option<(String, String)>,
+    scripts: VecDeque<CompileScript>,
+    tasks: Vec<MockTask>,
+    dss_responses: HashMap<String, MockResponse>,
+    requests: Vec<RecordedRequest>,
+    quota_headers: Vec<(&'static str, u64)>,
+}
+
+/// A mock server running in a background thread. It stops when dropped.
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Starts a server on a random local port
+    pub fn start() -> MockServer {
+        let state = Arc::new(Mutex::new(MockState {
+            version: VersionInfo {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                revision: String::from("mock"),
+                build_time: String::from("1970-01-01T00:00:00Z"),
+            },
+            capabilities: None,
+            credential: None,
+            scripts: VecDeque::new(),
+            tasks: Vec::new(),
+            dss_responses: HashMap::new(),
+            requests: Vec::new(),
+            quota_headers: Vec::new(),
+        }));
+
+        let listener =
+            std::net::TcpListener::bind("127.0.0.1:0").expect("fail to bind the mock server");
+        let addr = listener.local_addr().expect("fail to get the mock server address");
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let server_state = state.clone();
+        let thread = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("fail to create tokio runtime");
+            runtime.block_on(async move {
+                let make_service = make_service_fn(move |_| {
+                    let state = server_state.clone();
+                    async move {
+                        Ok::<_, Infallible>(service_fn(move |req| handle(state.clone(), req)))
+                    }
+                });
+                let server = hyper::Server::from_tcp(listener)
+                    .expect("fail to start the mock server")
+                    .serve(make_service)
+                    .with_graceful_shutdown(async {
+                        shutdown_rx.await.ok();
+                    });
+                if let Err(e) = server.await {
+                    tracing::error!("mock server error: {}", e);
+                }
+            });
+        });
+
+        MockServer { addr, state, shutdown: Some(shutdown), thread: Some(thread) }
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Creates a client connected to the server with dummy API keys
+    pub fn client(&self, runtime_version: &str) -> FuriosaClient {
+        FuriosaClient::with_credential(runtime_version, self.endpoint(), "mock-key", "mock-secret")
+            .expect("fail to create a client")
+            .poll_interval(Duration::from_millis(10))
+    }
+
+    /// Queues a script for the next submitted compile task. Tasks without scripts succeed
+    /// with an empty artifact.
+    pub fn script_compile(&self, script: CompileScript) {
+        self.state.lock().unwrap().scripts.push_back(script);
+    }
+
+    /// Sets the response of a DSS operation (e.g., 'optimize', 'quantize')
+    pub fn respond_dss(&self, operation: &str, response: MockResponse) {
+        self.state.lock().unwrap().dss_responses.insert(operation.to_string(), response);
+    }
+
+    pub fn set_version(&self, version: VersionInfo) {
+        self.state.lock().unwrap().version = version;
+    }
+
+    pub fn set_capabilities(&self, capabilities: Capabilities) {
+        self.state.lock().unwrap().capabilities = Some(capabilities);
+    }
+
+    /// Sends the rate limit and quota headers in every response
+    pub fn set_quota(&self, rate_limit: u64, rate_remaining: u64, quota_remaining: u64) {
+        self.state.lock().unwrap().quota_headers = vec![
+            ("x-ratelimit-limit", rate_limit),
+            ("x-ratelimit-remaining", rate_remaining),
+            ("x-furiosaai-quota-remaining", quota_remaining),
+        ];
+    }
+
+    /// Rejects requests without the API keys with 401
+    pub fn require_credential(&self, access_key_id: &str, secret_access_key: &str) {
+        self.state.lock().unwrap().credential =
+            Some((access_key_id.to_string(), secret_access_key.to_string()));
+    }
+
+    /// Returns the requests received so far
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Returns the compile tasks submitted so far
+    pub fn tasks(&self) -> Vec<CompileTask> {
+        self.state.lock().unwrap().tasks.iter().map(|t| t.task.clone()).collect()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+async fn handle(
+    state: Arc<Mutex<MockState>>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let query = req.uri().query().map(|q| q.to_string());
+    let headers: Vec<(String, String)> = req
+        .headers()
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_str().unwrap_or_default().to_string()))
+        .collect();
+    let body = hyper::body::to_bytes(req.into_body()).await.unwrap_or_default().to_vec();
+    let request = RecordedRequest { method: method.to_string(), path, query, headers, body };
+
+    let (response, delay) = {
+        let mut state = state.lock().unwrap();
+        let (mut response, delay) = route(&mut state, &method, &request);
+        for (name, value) in &state.quota_headers {
+            response.headers_mut().insert(*name, (*value).into());
+        }
+        state.requests.push(request);
+        (response, delay)
+    };
+    if delay > Duration::from_millis(0) {
+        tokio::time::sleep(delay).await;
+    }
+    Ok(response)
+}
+
+fn route(
+    state: &mut MockState,
+    method: &Method,
+    request: &RecordedRequest,
+) -> (Response<Body>, Duration) {
+    let no_delay = Duration::from_millis(0);
+    let path = request.path.as_str();
+    if path == "/version" {
+        return (json_response(StatusCode::OK, &state.version), no_delay);
+    }
+
+    if let Some((key, secret)) = &state.credential {
+        if request.header(ACCESS_KEY_ID_HTTP_HEADER) != Some(key.as_str())
+            || request.header(SECRET_ACCESS_KEY_HTTP_HEADER) != Some(secret.as_str())
+        {
+            let response = MockResponse::error(401, "Unauthorized", "invalid API keys");
+            return (mock_response(&response), no_delay);
+        }
+    }
+
+    if path == "/capabilities" {
+        return match &state.capabilities {
+            Some(capabilities) => (json_response(StatusCode::OK, capabilities), no_delay),
+            None => (not_found(path), no_delay),
+        };
+    }
+
+    if let Some(operation) = path.strip_prefix(DSS_PATH) {
+        return match (method, state.dss_responses.get(operation)) {
+            (&Method::POST, Some(response)) => (mock_response(response), no_delay),
+            _ => (not_found(path), no_delay),
+        };
+    }
+
+    if path == COMPILER_TASKS_PATH {
+        return match *method {
+            Method::POST => submit_task(state, request),
+            Method::GET => (list_tasks(state, request), no_delay),
+            _ => (not_found(path), no_delay),
+        };
+    }
+
+    let rest = match path.strip_prefix(COMPILER_TASKS_PATH).and_then(|p| p.strip_prefix('/')) {
+        Some(rest) => rest,
+        None => return (not_found(path), no_delay),
+    };
+    let mut segments = rest.splitn(2, '/');
+    let task_id = segments.next().unwrap_or_default();
+    let sub_path = segments.next();
+    let task = match state.tasks.iter_mut().find(|t| t.task.task_id == task_id) {
+        Some(task) => task,
+        None => return (not_found(path), no_delay),
+    };
+    let delay = task.script.delay;
+
+    let response = match (method, sub_path) {
+        (&Method::GET, None) => {
+            if !task.script.poll_errors.is_empty() {
+                let error = task.script.poll_errors.remove(0);
+                return (mock_response(&error), delay);
+            }
+            task.advance();
+            json_response(StatusCode::OK, &task.task)
+        }
+        (&Method::DELETE, None) => {
+            task.task.phase = CompileTaskPhase::Failed;
+            task.task.error_message = Some(String::from("cancelled"));
+            task.task.finish_time = Some(now());
+            json_response(StatusCode::OK, &task.task)
+        }
+        (&Method::GET, Some("logs")) => Response::new(Body::from(task.script.logs.clone())),
+        (&Method::GET, Some(artifact)) if artifact.starts_with("artifacts/") => {
+            if task.task.phase != CompileTaskPhase::Succeeded {
+                not_found(path)
+            } else if task.downloads > 0 && !task.script.resume_errors.is_empty() {
+                mock_response(&task.script.resume_errors.remove(0))
+            } else {
+                task.downloads += 1;
+                let name = &artifact["artifacts/".len()..];
+                artifact_response(task, name, request.header("range"))
+            }
+        }
+        _ => not_found(path),
+    };
+    (response, delay)
+}
+
+fn submit_task(state: &mut MockState, request: &RecordedRequest) -> (Response<Body>, Duration) {
+    let script = state.scripts.pop_front().unwrap_or_else(|| CompileScript::succeed(Vec::new()));
+    let delay = script.delay;
+    if let Some(error) = &script.submit_error {
+        return (mock_response(error), delay);
+    }
+
+    let mut task = MockTask {
+        task: CompileTask {
+            version: 1,
+            task_id: Uuid::new_v4().to_hyphenated().to_string(),
+            phase: CompileTaskPhase::Pending,
+            submit_time: now(),
+            start_time: None,
+            finish_time: None,
+            progress: 0.0,
+            error_message: None,
+            labels: multipart_text(&request.body, "labels")
+                .and_then(|labels| serde_json::from_str(&labels).ok())
+                .unwrap_or_default(),
+        },
+        script,
+        next_phase: 0,
+        downloads: 0,
+    };
+    task.advance();
+    let response = json_response(StatusCode::OK, &task.task);
+    state.tasks.push(task);
+    (response, delay)
+}
+
+impl MockTask {
+    /// Moves the task to the next scripted phase
+    fn advance(&mut self) {
+        if self.task.phase.is_completed() {
+            return;
+        }
+        let phases = &self.script.phases;
+        let phase = phases
+            .get(self.next_phase)
+            .or_else(|| phases.last())
+            .copied()
+            .unwrap_or(CompileTaskPhase::Succeeded);
+        self.next_phase += 1;
+        self.task.phase = phase;
+        self.task.progress = (self.next_phase as f32 / phases.len().max(1) as f32).min(1.0);
+        if phase != CompileTaskPhase::Pending && self.task.start_time.is_none() {
+            self.task.start_time = Some(now());
+        }
+        if phase == CompileTaskPhase::Failed {
+            self.task.error_message = Some(String::from("compilation failed"));
+        }
+        if phase.is_completed() {
+            self.task.finish_time = Some(now());
+        }
+    }
+}
+
+fn artifact_response(task: &MockTask, name: &str, range: Option<&str>) -> Response<Body> {
+    let script = &task.script;
+    let artifact = match TargetIr::iter().find(|ir| ir.artifact_name() == name) {
+        Some(target_ir) if script.missing_irs.contains(&target_ir) => return not_found(name),
+        Some(target_ir) => script.ir_artifacts.get(&target_ir).unwrap_or(&script.artifact),
+        None => &script.artifact,
+    };
+    let offset = range
+        .and_then(|r| r.strip_prefix("bytes="))
+        .and_then(|r| r.trim_end_matches('-').parse::<usize>().ok())
+        .unwrap_or(0);
+    let checksum = format!("{:x}", Sha256::digest(artifact));
+    if offset > 0 && offset >= artifact.len() {
+        return Response::builder()
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header("content-range", format!("bytes */{}", artifact.len()))
+            .body(Body::empty())
+            .expect("fail to build a response");
+    }
+    let remaining = artifact[offset..].to_vec();
+
+    let builder = Response::builder().header(CHECKSUM_HTTP_HEADER, checksum);
+    let builder = if offset > 0 {
+        builder.status(StatusCode::PARTIAL_CONTENT).header(
+            "content-range",
+            format!("bytes {}-{}/{}", offset, artifact.len().max(1) - 1, artifact.len()),
+        )
+    } else {
+        builder.status(StatusCode::OK)
+    };
+
+    // an interrupted body has no length, so that the interruption is noticed even after
+    // the whole artifact has been sent
+    let (builder, body) = match task.script.interrupt_download_at {
+        Some(cut) if task.downloads == 1 && cut <= remaining.len() => {
+            let head = futures_util::stream::iter(vec![Ok(remaining[..cut].to_vec())]);
+            // gives the sent part a chance to be flushed before dropping the connection
+            let tail = futures_util::stream::once(async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Err(std::io::Error::new(std::io::ErrorKind::ConnectionReset, "interrupted"))
+            });
+            (builder, Body::wrap_stream(head.chain(tail)))
+        }
+        _ => (builder.header("content-length", remaining.len()), Body::from(remaining)),
+    };
+    builder.body(body).expect("fail to build a response")
+}
+
+fn mock_response(response: &MockResponse) -> Response<Body> {
+    match response {
+        MockResponse::Bytes(bytes) => Response::new(Body::from(bytes.clone())),
+        MockResponse::Error { status, error_code, message } => {
+            let status = StatusCode::from_u16(*status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            let payload = ApiResponse {
+                error_code: error_code.clone(),
+                message: message.clone(),
+                trace_id: Some(Uuid::new_v4().to_hyphenated().to_string()),
+            };
+            json_response(status, &payload)
+        }
+        MockResponse::RateLimited { retry_after } => {
+            let error = MockResponse::error(429, "TooManyRequests", "too many requests");
+            let mut response = mock_response(&error);
+            response.headers_mut().insert("retry-after", (*retry_after).into());
+            response
+        }
+    }
+}
+
+/// Filters the tasks by the query of `TaskFilter`. Page tokens are offsets.
+fn list_tasks(state: &MockState, request: &RecordedRequest) -> Response<Body> {
+    let mut filter = TaskFilter::new();
+    let mut offset = 0;
+    let url = reqwest::Url::parse(&format!(
+        "http://mock{}?{}",
+        request.path,
+        request.query.as_deref().unwrap_or_default()
+    ))
+    .expect("invalid request uri");
+    for (key, value) in url.query_pairs() {
+        match (key.as_ref(), value.parse::<CompileTaskPhase>(), value.parse::<i64>()) {
+            ("phase", Ok(phase), _) => filter.phases.push(phase),
+            ("submitted_after", _, Ok(after)) => filter.submitted_after = Some(after),
+            ("submitted_before", _, Ok(before)) => filter.submitted_before = Some(before),
+            ("page_size", _, Ok(size)) => filter.page_size = Some(size as u32),
+            ("page_token", _, Ok(token)) => offset = token as usize,
+            ("label", _, _) => {
+                let mut kv = value.splitn(2, '=');
+                filter = filter.label(kv.next().unwrap_or_default(), kv.next().unwrap_or_default());
+            }
+            _ => {}
+        }
+    }
+    let tasks: Vec<CompileTask> =
+        state.tasks.iter().map(|t| &t.task).filter(|t| filter.matches(t)).cloned().collect();
+    let end =
+        filter.page_size.map_or(tasks.len(), |size| (offset + size as usize).min(tasks.len()));
+    let page = TaskPage {
+        tasks: tasks.get(offset..end).unwrap_or_default().to_vec(),
+        next_page_token: if end < tasks.len() { Some(end.to_string()) } else { None },
+    };
+    json_response(StatusCode::OK, &page)
+}
+
+/// Returns the text of the part `name` in a multipart body
+fn multipart_text(body: &[u8], name: &str) -> Option<String> {
+    let body = String::from_utf8_lossy(body);
+    let start = body.find(&format!("name=\"{}\"", name))?;
+    let text = &body[start..];
+    let text = &text[text.find("\r\n\r\n")? + 4..];
+    Some(text[..text.find("\r\n--")?].to_string())
+}
+
+fn not_found(path: &str) -> Response<Body> {
+    mock_response(&MockResponse::error(404, "NotFound", &format!("{} not found", path)))
+}
+
+fn json_response<T: serde::Serialize>(status: StatusCode, value: &T) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_vec(value).expect("fail to serialize")))
+        .expect("fail to build a response")
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
@@ -0,0 +1,289 @@
+//! A minimal reader of TFLite models
+//!
+//! It walks the flatbuffer of `tflite::Model` without generated code and only decodes the
+//! operators of each subgraph.
+
+use std::convert::TryInto;
+
+use crate::ClientError;
+
+// Vtable slots from https://github.com/tensorflow/tensorflow/blob/master/tensorflow/lite/schema/schema.fbs
+const MODEL_OPERATOR_CODES_SLOT: usize = 1;
+const MODEL_SUBGRAPHS_SLOT: usize = 2;
+const OPERATOR_CODE_DEPRECATED_BUILTIN_CODE_SLOT: usize = 0;
+const OPERATOR_CODE_CUSTOM_CODE_SLOT: usize = 1;
+const OPERATOR_CODE_BUILTIN_CODE_SLOT: usize = 3;
+const SUBGRAPH_TENSORS_SLOT: usize = 0;
+const SUBGRAPH_OPERATORS_SLOT: usize = 3;
+const OPERATOR_OPCODE_INDEX_SLOT: usize = 0;
+const OPERATOR_OUTPUTS_SLOT: usize = 2;
+const TENSOR_NAME_SLOT: usize = 3;
+
+const TFLITE_FILE_IDENTIFIER: &[u8] = b"TFL3";
+const CUSTOM_BUILTIN_CODE: i32 = 32;
+
+/// Names of `tflite::BuiltinOperator` indexed by their codes
+static BUILTIN_OPERATORS: &[&str] = &[
+    "ADD",
+    "AVERAGE_POOL_2D",
+    "CONCATENATION",
+    "CONV_2D",
+    "DEPTHWISE_CONV_2D",
+    "DEPTH_TO_SPACE",
+    "DEQUANTIZE",
+    "EMBEDDING_LOOKUP",
+    "FLOOR",
+    "FULLY_CONNECTED",
+    "HASHTABLE_LOOKUP",
+    "L2_NORMALIZATION",
+    "L2_POOL_2D",
+    "LOCAL_RESPONSE_NORMALIZATION",
+    "LOGISTIC",
+    "LSH_PROJECTION",
+    "LSTM",
+    "MAX_POOL_2D",
+    "MUL",
+    "RELU",
+    "RELU_N1_TO_1",
+    "RELU6",
+    "RESHAPE",
+    "RESIZE_BILINEAR",
+    "RNN",
+    "SOFTMAX",
+    "SPACE_TO_DEPTH",
+    "SVDF",
+    "TANH",
+    "CONCAT_EMBEDDINGS",
+    "SKIP_GRAM",
+    "CALL",
+    "CUSTOM",
+    "EMBEDDING_LOOKUP_SPARSE",
+    "PAD",
+    "UNIDIRECTIONAL_SEQUENCE_RNN",
+    "GATHER",
+    "BATCH_TO_SPACE_ND",
+    "SPACE_TO_BATCH_ND",
+    "TRANSPOSE",
+    "MEAN",
+    "SUB",
+    "DIV",
+    "SQUEEZE",
+    "UNIDIRECTIONAL_SEQUENCE_LSTM",
+    "STRIDED_SLICE",
+    "BIDIRECTIONAL_SEQUENCE_RNN",
+    "EXP",
+    "TOPK_V2",
+    "SPLIT",
+    "LOG_SOFTMAX",
+    "DELEGATE",
+    "BIDIRECTIONAL_SEQUENCE_LSTM",
+    "CAST",
+    "PRELU",
+    "MAXIMUM",
+    "ARG_MAX",
+    "MINIMUM",
+    "LESS",
+    "NEG",
+    "PADV2",
+    "GREATER",
+    "GREATER_EQUAL",
+    "LESS_EQUAL",
+    "SELECT",
+    "SLICE",
+    "SIN",
+    "TRANSPOSE_CONV",
+    "SPARSE_TO_DENSE",
+    "TILE",
+    "EXPAND_DIMS",
+    "EQUAL",
+    "NOT_EQUAL",
+    "LOG",
+    "SUM",
+    "SQRT",
+    "RSQRT",
+    "SHAPE",
+    "POW",
+    "ARG_MIN",
+    "FAKE_QUANT",
+    "REDUCE_PROD",
+    "REDUCE_MAX",
+    "PACK",
+    "LOGICAL_OR",
+    "ONE_HOT",
+    "LOGICAL_AND",
+    "LOGICAL_NOT",
+    "UNPACK",
+    "REDUCE_MIN",
+    "FLOOR_DIV",
+    "REDUCE_ANY",
+    "SQUARE",
+    "ZEROS_LIKE",
+    "FILL",
+    "FLOOR_MOD",
+    "RANGE",
+    "RESIZE_NEAREST_NEIGHBOR",
+    "LEAKY_RELU",
+    "SQUARED_DIFFERENCE",
+    "MIRROR_PAD",
+    "ABS",
+    "SPLIT_V",
+    "UNIQUE",
+    "CEIL",
+    "REVERSE_V2",
+    "ADD_N",
+    "GATHER_ND",
+    "COS",
+    "WHERE",
+    "RANK",
+    "ELU",
+    "REVERSE_SEQUENCE",
+    "MATRIX_DIAG",
+    "QUANTIZE",
+    "MATRIX_SET_DIAG",
+    "ROUND",
+    "HARD_SWISH",
+    "IF",
+    "WHILE",
+    "NON_MAX_SUPPRESSION_V4",
+    "NON_MAX_SUPPRESSION_V5",
+    "SCATTER_ND",
+    "SELECT_V2",
+    "DENSIFY",
+    "SEGMENT_SUM",
+    "BATCH_MATMUL",
+];
+
+#[derive(Debug, Clone)]
+pub struct TfliteOperator {
+    /// Name of the first output tensor, which identifies the operator
+    pub name: String,
+    pub op_type: String,
+}
+
+pub fn is_tflite(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && &bytes[4..8] == TFLITE_FILE_IDENTIFIER
+}
+
+pub fn parse_operators(bytes: &[u8]) -> Result<Vec<TfliteOperator>, ClientError> {
+    let buf = Buffer(bytes);
+    let model = buf.root()?;
+
+    let mut op_types = Vec::new();
+    for code in buf.tables(model, MODEL_OPERATOR_CODES_SLOT)? {
+        let deprecated = buf.scalar::<1>(code, OPERATOR_CODE_DEPRECATED_BUILTIN_CODE_SLOT)?;
+        let builtin = buf.scalar::<4>(code, OPERATOR_CODE_BUILTIN_CODE_SLOT)?;
+        // `builtin_code` is only used by new models; old ones keep the code in a byte
+        let builtin = i32::from_le_bytes(builtin.unwrap_or([0; 4]))
+            .max(i32::from(deprecated.map(|b| b[0] as i8).unwrap_or(0)));
+        let op_type = if builtin == CUSTOM_BUILTIN_CODE {
+            buf.string(code, OPERATOR_CODE_CUSTOM_CODE_SLOT)?.unwrap_or_else(|| "CUSTOM".into())
+        } else {
+            BUILTIN_OPERATORS
+                .get(builtin as usize)
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| format!("BUILTIN_{}", builtin))
+        };
+        op_types.push(op_type);
+    }
+
+    let mut operators = Vec::new();
+    for subgraph in buf.tables(model, MODEL_SUBGRAPHS_SLOT)? {
+        let tensors = buf.tables(subgraph, SUBGRAPH_TENSORS_SLOT)?;
+        for (idx, op) in buf.tables(subgraph, SUBGRAPH_OPERATORS_SLOT)?.into_iter().enumerate() {
+            let opcode_index = buf.scalar::<4>(op, OPERATOR_OPCODE_INDEX_SLOT)?;
+            let opcode_index = u32::from_le_bytes(opcode_index.unwrap_or([0; 4])) as usize;
+            let op_type = op_types.get(opcode_index).cloned().ok_or_else(|| {
+                ClientError::InvalidModel(format!("invalid opcode index {}", opcode_index))
+            })?;
+            let name = match buf.ints(op, OPERATOR_OUTPUTS_SLOT)?.first() {
+                Some(&tensor) if tensor >= 0 && (tensor as usize) < tensors.len() => {
+                    buf.string(tensors[tensor as usize], TENSOR_NAME_SLOT)?.unwrap_or_default()
+                }
+                _ => format!("operator_{}", idx),
+            };
+            operators.push(TfliteOperator { name, op_type });
+        }
+    }
+    Ok(operators)
+}
+
+struct Buffer<'a>(&'a [u8]);
+
+impl<'a> Buffer<'a> {
+    fn bytes<const N: usize>(&self, pos: usize) -> Result<[u8; N], ClientError> {
+        self.0
+            .get(pos..pos + N)
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| ClientError::InvalidModel("truncated TFLite model".to_string()))
+    }
+
+    fn u32(&self, pos: usize) -> Result<usize, ClientError> {
+        Ok(u32::from_le_bytes(self.bytes(pos)?) as usize)
+    }
+
+    fn root(&self) -> Result<usize, ClientError> {
+        if !is_tflite(self.0) {
+            return Err(ClientError::InvalidModel("not a TFLite model".to_string()));
+        }
+        self.u32(0)
+    }
+
+    /// Returns the absolute position of a field in the table if it is present
+    fn field(&self, table: usize, slot: usize) -> Result<Option<usize>, ClientError> {
+        let vtable = table as i64 - i64::from(i32::from_le_bytes(self.bytes(table)?));
+        if vtable < 0 {
+            return Err(ClientError::InvalidModel("invalid TFLite vtable".to_string()));
+        }
+        let vtable = vtable as usize;
+        let vtable_size = u16::from_le_bytes(self.bytes(vtable)?) as usize;
+        let entry = 4 + 2 * slot;
+        if entry + 2 > vtable_size {
+            return Ok(None);
+        }
+        match u16::from_le_bytes(self.bytes(vtable + entry)?) {
+            0 => Ok(None),
+            offset => Ok(Some(table + offset as usize)),
+        }
+    }
+
+    fn scalar<const N: usize>(
+        &self,
+        table: usize,
+        slot: usize,
+    ) -> Result<Option<[u8; N]>, ClientError> {
+        self.field(table, slot)?.map(|pos| self.bytes(pos)).transpose()
+    }
+
+    /// Returns the position of the first element and the length of a vector field
+    fn vector(&self, table: usize, slot: usize) -> Result<(usize, usize), ClientError> {
+        match self.field(table, slot)? {
+            Some(pos) => {
+                let vector = pos + self.u32(pos)?;
+                Ok((vector + 4, self.u32(vector)?))
+            }
+            None => Ok((0, 0)),
+        }
+    }
+
+    fn tables(&self, table: usize, slot: usize) -> Result<Vec<usize>, ClientError> {
+        let (start, len) = self.vector(table, slot)?;
+        (0..len).map(|i| start + 4 * i).map(|pos| Ok(pos + self.u32(pos)?)).collect()
+    }
+
+    fn ints(&self, table: usize, slot: usize) -> Result<Vec<i32>, ClientError> {
+        let (start, len) = self.vector(table, slot)?;
+        (0..len).map(|i| Ok(i32::from_le_bytes(self.bytes(start + 4 * i)?))).collect()
+    }
+
+    fn string(&self, table: usize, slot: usize) -> Result<Option<String>, ClientError> {
+        if self.field(table, slot)?.is_none() {
+            return Ok(None);
+        }
+        let (start, len) = self.vector(table, slot)?;
+        let bytes = self
+            .0
+            .get(start..start + len)
+            .ok_or_else(|| ClientError::InvalidModel("truncated TFLite model".to_string()))?;
+        Ok(Some(String::from_utf8_lossy(bytes).to_string()))
+    }
+}
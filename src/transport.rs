@@ -0,0 +1,56 @@
+//! HTTP layer of `FuriosaClient`
+//!
+//! Every request of the client is sent through a `Transport`, so middlewares (e.g., logging,
+//! retrying or cassettes), custom connectors and in-memory fakes can be plugged in with
+//! `FuriosaClient::transport`. Requests and responses are `reqwest` types, of which bodies can
+//! be streamed (`reqwest::Body::wrap_stream` and `Response::bytes_stream`). A response built
+//! from `http::Response` can be returned with `reqwest::Response::from`.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use reqwest::{Request, Response};
+
+use crate::{ClientError, FURIOSA_CLIENT_USER_AGENT};
+
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Response, ClientError>> + Send + 'a>>;
+
+pub trait Transport: Send + Sync {
+    /// Sends a request and returns the response of any status
+    fn send(&self, request: Request) -> TransportFuture<'_>;
+}
+
+/// The default transport over `reqwest::Client`
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+impl ReqwestTransport {
+    pub fn new() -> ReqwestTransport {
+        let client = reqwest::Client::builder()
+            .user_agent(FURIOSA_CLIENT_USER_AGENT.as_str())
+            .build()
+            .expect("fail to create HTTP Client");
+        ReqwestTransport::from_client(client)
+    }
+
+    /// Uses a client with custom settings (e.g., proxies, timeouts and TLS)
+    pub fn from_client(client: reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { client }
+    }
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> Self {
+        ReqwestTransport::new()
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: Request) -> TransportFuture<'_> {
+        Box::pin(async move {
+            self.client.execute(request).await.map_err(|e| ClientError::Transport(format!("{}", e)))
+        })
+    }
+}
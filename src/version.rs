@@ -0,0 +1,92 @@
+//! Compatibility between the SDK (runtime) version and the server version
+//!
+//! Pre-release and build metadata are ignored, so `0.4.1-dev` is checked as `0.4.1`.
+//! The matrix lists the server versions which each SDK range works with. A server older than
+//! the lowest listed version lacks the API the SDK range relies on, so it's `Incompatible`,
+//! while a newer server isn't known to break it, so it's `Unknown`.
+
+use semver::{Version, VersionReq};
+use serde::Serialize;
+
+use crate::ClientError;
+
+/// Pairs of SDK version ranges and server versions which support them
+static COMPATIBILITY_MATRIX: &[(&str, &str)] = &[
+    (">=0.1.0, <0.2.0", ">=0.1.0, <0.2.0"),
+    (">=0.2.0, <0.4.0", ">=0.2.0, <0.4.0"),
+    (">=0.4.0, <0.5.0", ">=0.2.0, <0.5.0"),
+];
+
+#[derive(Serialize, Debug, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompatibilityStatus {
+    Compatible,
+    /// The server version is older than the versions listed for the SDK version
+    Incompatible,
+    /// The SDK version is not in the compatibility matrix, or the server version is newer than
+    /// the versions listed for it
+    Unknown,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Compatibility {
+    pub sdk_version: String,
+    pub server_version: String,
+    pub status: CompatibilityStatus,
+    /// Server versions which the matrix lists for the SDK version
+    pub required_server_version: Option<String>,
+}
+
+impl Compatibility {
+    pub fn check(sdk_version: &str, server_version: &str) -> Result<Compatibility, ClientError> {
+        let sdk = parse_version(sdk_version)
+            .map_err(|e| ClientError::InvalidRuntimeVersion(format!("{}", e)))?;
+        let server = parse_version(server_version).map_err(|e| {
+            ClientError::ApiError(format!("invalid server version '{}': {}", server_version, e))
+        })?;
+
+        let rule = COMPATIBILITY_MATRIX.iter().find(|(sdk_req, _)| {
+            VersionReq::parse(sdk_req).expect("invalid compatibility matrix").matches(&sdk)
+        });
+        let (status, required_server_version) = match rule {
+            Some((_, server_req)) => {
+                let matched = VersionReq::parse(server_req)
+                    .expect("invalid compatibility matrix")
+                    .matches(&server);
+                let status = if matched {
+                    CompatibilityStatus::Compatible
+                } else if matches!(lower_bound(server_req), Some(min) if server < min) {
+                    CompatibilityStatus::Incompatible
+                } else {
+                    CompatibilityStatus::Unknown
+                };
+                (status, Some(server_req.to_string()))
+            }
+            None => (CompatibilityStatus::Unknown, None),
+        };
+
+        Ok(Compatibility {
+            sdk_version: sdk.to_string(),
+            server_version: server.to_string(),
+            status,
+            required_server_version,
+        })
+    }
+
+    /// Returns false only if the versions are known to be incompatible
+    pub fn is_compatible(&self) -> bool {
+        self.status != CompatibilityStatus::Incompatible
+    }
+}
+
+/// Returns the version of the `>=` comparator of a range in the matrix
+fn lower_bound(req: &str) -> Option<Version> {
+    req.split(',')
+        .filter_map(|comparator| comparator.trim().strip_prefix(">="))
+        .find_map(|version| Version::parse(version.trim()).ok())
+}
+
+fn parse_version(version: &str) -> Result<Version, semver::SemVerError> {
+    let version = Version::parse(version.trim_start_matches('v'))?;
+    Ok(Version::new(version.major, version.minor, version.patch))
+}
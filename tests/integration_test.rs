@@ -1,6 +1,9 @@
 use furiosa_client::{
-    get_endpoint_from_env, CalibrateRequest, ClientError, CompileRequest, FuriosaClient,
-    OptimizeRequest, QuantizeRequest, TargetIr, VersionInfo, FURIOSA_API_ENDPOINT_ENV,
+    check_operators, get_endpoint_from_env, validate_labels, CalibrateRequest, Capabilities,
+    ClientError, Compatibility, CompatibilityStatus, CompileCache, CompileOutput, CompileRequest,
+    FuriosaClient, Labels, ModelFormat, OptimizeRequest, QuantizeRequest, RateLimit, TargetIr,
+    Transport, TransportFuture, VersionInfo, FURIOSA_API_ENDPOINT_ENV, MAX_LABELS,
+    MAX_LABEL_KEY_LEN, MAX_LABEL_VALUE_LEN,
 };
 use serde_json::Value;
 use std::io;
@@ -14,6 +17,106 @@ async fn test_version() -> Result<(), ClientError> {
     Ok(())
 }
 
+#[test]
+fn test_compatibility() -> Result<(), ClientError> {
+    let compatibility = Compatibility::check("0.2.1", "0.2.0")?;
+    assert_eq!(compatibility.status, CompatibilityStatus::Compatible);
+
+    // a server newer than the listed range isn't known to be incompatible
+    let compatibility = Compatibility::check("0.4.0", "0.5.0-dev")?;
+    assert_eq!(compatibility.status, CompatibilityStatus::Unknown);
+    assert_eq!(compatibility.required_server_version.as_deref(), Some(">=0.2.0, <0.5.0"));
+    assert!(compatibility.is_compatible());
+
+    // but an older one is
+    let compatibility = Compatibility::check("0.4.0", "0.1.9")?;
+    assert_eq!(compatibility.status, CompatibilityStatus::Incompatible);
+    assert!(!compatibility.is_compatible());
+
+    let compatibility = Compatibility::check("9.0.0", "0.2.0")?;
+    assert_eq!(compatibility.status, CompatibilityStatus::Unknown);
+    assert!(compatibility.is_compatible());
+
+    assert!(Compatibility::check("0.4.0", "unknown").is_err());
+    Ok(())
+}
+
+/// Answers every request with the same version without network
+struct VersionTransport;
+
+impl Transport for VersionTransport {
+    fn send(&self, request: reqwest::Request) -> TransportFuture<'_> {
+        Box::pin(async move {
+            assert_eq!(request.url().path(), "/version");
+            let body = r#"{"version": "0.4.0", "revision": "abc", "build_time": "now"}"#;
+            Ok(http::Response::builder().status(200).body(body).unwrap().into())
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_transport() -> Result<(), ClientError> {
+    let client = FuriosaClient::with_credential("0.4.0", "http://unreachable", "key", "secret")?
+        .transport(VersionTransport);
+    assert_eq!(&client.server_version().await?.revision, "abc");
+    Ok(())
+}
+
+#[test]
+fn test_capabilities() {
+    let capabilities: Capabilities = serde_json::from_str(
+        r#"{
+  "target_irs": ["dfg", "ENF"],
+  "dss_operations": ["optimize", "quantize"],
+  "max_upload_size": 1048576,
+  "api_versions": { "compiler": ["v1alpha1"], "dss": ["v1"] },
+  "unknown_field": true
+}"#,
+    )
+    .expect("fail to parse JSON");
+
+    assert!(capabilities.supports_target_ir(TargetIr::Enf));
+    assert!(!capabilities.supports_target_ir(TargetIr::Lir));
+    assert!(capabilities.supports_dss_operation("quantize"));
+    assert!(!capabilities.supports_dss_operation("build-calibration-model"));
+    assert!(capabilities.supports_api("compiler", "v1alpha1"));
+    assert!(!capabilities.supports_api("compiler", "v1"));
+    assert!(capabilities.npu_presets.is_none());
+}
+
+#[test]
+fn test_capabilities_without_fields() {
+    // omitted fields don't reject anything
+    let capabilities: Capabilities = serde_json::from_str("{}").expect("fail to parse JSON");
+    assert!(capabilities.supports_target_ir(TargetIr::Lir));
+    assert!(capabilities.supports_dss_operation("quantize"));
+    assert!(capabilities.supports_api("compiler", "v1alpha1"));
+    assert!(capabilities.max_upload_size.is_none());
+
+    let capabilities: Capabilities =
+        serde_json::from_str(r#"{"target_irs": [], "api_versions": {"dss": ["v1"]}}"#)
+            .expect("fail to parse JSON");
+    assert!(!capabilities.supports_target_ir(TargetIr::Enf));
+    assert!(capabilities.supports_api("compiler", "v1alpha1"));
+    assert!(!capabilities.supports_api("dss", "v2"));
+}
+
+#[test]
+fn test_rate_limit_rejects_invalid_rates() {
+    assert!(RateLimit::per_second(2.0).is_ok());
+    assert!(RateLimit::per_minute(30.0).is_ok());
+    assert!(RateLimit::per_second(0.0).is_err());
+    assert!(RateLimit::per_second(-1.0).is_err());
+    assert!(RateLimit::per_second(f64::NAN).is_err());
+    assert!(RateLimit::per_second(f64::INFINITY).is_err());
+    // the wait for a token would overflow `Duration`
+    assert!(RateLimit::per_second(1e-300).is_err());
+    assert!(RateLimit::per_minute(1.0 / 24.0 / 60.0).is_ok());
+    assert!(RateLimit::per_minute(0.0).is_err());
+    assert!(RateLimit::per_minute(-30.0).is_err());
+    assert!(RateLimit::per_minute(f64::NAN).is_err());
+}
+
 #[test]
 fn test_get_endpoint_from_env() -> Result<(), ClientError> {
     let origin_endpoint = get_endpoint_from_env()?;
@@ -28,6 +131,191 @@ fn test_get_endpoint_from_env() -> Result<(), ClientError> {
     Ok(())
 }
 
+#[test]
+fn test_calibrate_request_from_path() -> Result<(), ClientError> {
+    let request = CalibrateRequest::from_path("models/quantization/test.onnx")?;
+    assert_eq!(&request.filename, "test.onnx");
+    assert_eq!(request.input_tensors, vec!["input".to_string()]);
+
+    let source = std::fs::read("models/quantization/test.onnx")?;
+    let request = QuantizeRequest::from_model(source, Default::default())?;
+    assert_eq!(&request.filename, "noname");
+    assert_eq!(request.input_tensors, vec!["input".to_string()]);
+
+    assert!(CalibrateRequest::from_model(vec![0xffu8; 4]).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_calibrate_request_skips_tensor_data() -> Result<(), ClientError> {
+    fn field(num: u8, bytes: &[u8]) -> Vec<u8> {
+        let mut field = vec![num << 3 | 2];
+        let mut len = bytes.len();
+        while len >= 0x80 {
+            field.push((len as u8) | 0x80);
+            len >>= 7;
+        }
+        field.push(len as u8);
+        field.extend_from_slice(bytes);
+        field
+    }
+    // an initializer larger than the bounded read, whose name follows the tensor data
+    let mut tensor = field(9, &vec![0u8; 8 << 20]);
+    tensor.extend(field(8, b"weight"));
+    let mut graph = field(1, b"node");
+    graph.extend(field(11, &field(1, b"input")));
+    graph.extend(field(11, &field(1, b"weight")));
+    graph.extend(field(5, &tensor));
+    let mut model = vec![0x08, 0x07];
+    model.extend(field(7, &graph));
+
+    let path = std::env::temp_dir().join(format!("furiosa-large-{}.onnx", std::process::id()));
+    std::fs::write(&path, &model)?;
+    let request = CalibrateRequest::from_path(&path)?;
+    assert_eq!(request.input_tensors, vec!["input".to_string()]);
+
+    std::fs::write(&path, &model[..model.len() - 16])?;
+    assert!(matches!(CalibrateRequest::from_path(&path), Err(ClientError::InvalidModel(_))));
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn test_check_operators() -> Result<(), ClientError> {
+    let target_npu_spec: Value =
+        serde_yaml::from_str(include_str!("../configs/64dpes.yml")).unwrap();
+
+    let binary = std::fs::read("models/tflite/MNISTnet_uint8_quant.tflite")?;
+    let report = check_operators(&target_npu_spec, &binary)?;
+    assert_eq!(report.format, ModelFormat::Tflite);
+    assert!(report.is_verified(), "{:?}", report);
+    assert!(report.cpu_fallback_operators.is_empty());
+
+    let binary = std::fs::read("models/tflite/MNISTnet_uint8_quant_without_softmax.tflite")?;
+    let request = CompileRequest::new(target_npu_spec.clone(), binary);
+    assert!(request.check_operators()?.is_verified());
+
+    // no ONNX operator is verified yet, so they are unknown rather than unsupported
+    let binary = std::fs::read("models/quantization/test.onnx")?;
+    let report = check_operators(&target_npu_spec, &binary)?;
+    assert_eq!(report.format, ModelFormat::Onnx);
+    assert!(report.num_operators > 0);
+    assert_eq!(report.unknown_operators.len(), report.num_operators);
+    assert!(!report.is_verified());
+
+    // a spec which only partially matches the bundled one isn't verified
+    let mut other_spec = target_npu_spec.clone();
+    other_spec["mac_width"] = 64.into();
+    assert!(check_operators(&other_spec, &binary).is_err());
+    let unknown_spec: Value = serde_json::from_str(r#"{"num_slices": 1}"#).unwrap();
+    assert!(check_operators(&unknown_spec, &binary).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_compile_cache_eviction() -> Result<(), ClientError> {
+    let dir = std::env::temp_dir().join(format!("furiosa-cache-{}", std::process::id()));
+    let cache = CompileCache::open(&dir)?.max_size(10);
+    // keys are SHA-256 digests in hex
+    let (a, b, c) = ("a".repeat(64), "b".repeat(64), "c".repeat(64));
+
+    cache.put(&a, b"aaaaaa")?;
+    cache.put(&b, b"bbbb")?;
+    assert!(cache.get(&a).is_some());
+    // 'b' is the least recently used one
+    cache.put(&c, b"cccc")?;
+    assert!(cache.get(&b).is_none());
+    assert_eq!(cache.size(), 10);
+
+    let cache = CompileCache::open(&dir)?;
+    assert_eq!(cache.get(&a).as_deref(), Some(&b"aaaaaa"[..]));
+    assert_eq!(cache.get(&c).as_deref(), Some(&b"cccc"[..]));
+    cache.clear()?;
+    assert_eq!(cache.size(), 0);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_compile_cache_shared() -> Result<(), ClientError> {
+    let dir = std::env::temp_dir().join(format!("furiosa-cache-shared-{}", std::process::id()));
+    let first = CompileCache::open(&dir)?;
+    let second = CompileCache::open(&dir)?;
+
+    // keys which aren't hex digests can't escape the directory
+    assert!(first.put("../escaped", b"x").is_err());
+    assert!(first.put(&"A".repeat(64), b"x").is_err());
+    assert!(first.get("../index.json").is_none());
+    assert!(!dir.parent().unwrap().join("escaped").exists());
+
+    // caches sharing the directory see the changes of each other
+    let threads: Vec<_> = (0..8u8)
+        .map(|i| {
+            let dir = dir.clone();
+            std::thread::spawn(move || {
+                let cache = CompileCache::open(&dir).unwrap();
+                cache.put(&format!("{:064x}", i), &[i; 4]).unwrap();
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(first.size(), 32);
+    assert_eq!(second.get(&format!("{:064x}", 7)).as_deref(), Some(&[7u8; 4][..]));
+    second.clear()?;
+    assert_eq!(first.size(), 0);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_validate_labels() {
+    let mut labels = Labels::new();
+    labels.insert("git/sha".to_string(), "3f2a9c1".to_string());
+    labels.insert("model-version".to_string(), "1.2.0+rc1".to_string());
+    labels.insert("team".to_string(), String::new());
+    assert!(validate_labels(&labels).is_ok());
+
+    let invalid = |key: &str, value: &str| {
+        let labels: Labels = vec![(key.to_string(), value.to_string())].into_iter().collect();
+        matches!(validate_labels(&labels), Err(ClientError::InvalidLabel(_)))
+    };
+    assert!(invalid("", "value"));
+    assert!(invalid("-team", "value"));
+    assert!(invalid("team name", "value"));
+    assert!(invalid(&"k".repeat(MAX_LABEL_KEY_LEN + 1), "value"));
+    assert!(invalid("team", "a b"));
+    assert!(invalid("team", &"v".repeat(MAX_LABEL_VALUE_LEN + 1)));
+
+    let labels: Labels = (0..=MAX_LABELS).map(|i| (format!("key{}", i), String::new())).collect();
+    assert!(matches!(validate_labels(&labels), Err(ClientError::InvalidLabel(_))));
+}
+
+#[test]
+fn test_target_ir() -> Result<(), ClientError> {
+    let irs: Vec<TargetIr> = TargetIr::iter().collect();
+    assert_eq!(irs.len(), 6);
+    for ir in irs {
+        assert_eq!(ir.to_string().parse::<TargetIr>()?, ir);
+        assert_eq!(serde_json::to_string(&ir).unwrap(), format!("\"{}\"", ir));
+        assert_eq!(ir.artifact_name(), format!("output.{}", ir));
+    }
+    assert_eq!(serde_json::from_str::<TargetIr>("\"gir\"").unwrap(), TargetIr::Gir);
+    assert_eq!(TargetIr::Enf.content_type(), "application/octet-stream");
+
+    let text = CompileOutput::decode(TargetIr::Lir, b"lir".to_vec().into_boxed_slice());
+    assert_eq!(text.as_text(), Some("lir"));
+    let binary = CompileOutput::decode(TargetIr::Enf, b"enf".to_vec().into_boxed_slice());
+    assert_eq!(binary, CompileOutput::Binary(b"enf".to_vec().into_boxed_slice()));
+    let invalid = CompileOutput::decode(TargetIr::Gir, vec![0xff].into_boxed_slice());
+    assert_eq!(invalid.as_bytes(), &[0xff]);
+    assert!(invalid.as_text().is_none());
+    Ok(())
+}
+
 #[cfg(feature = "blocking")]
 #[test]
 #[ignore]
@@ -85,6 +373,7 @@ async fn test_compile_with_target_ir() {
 
     let result = client.compile(request).await;
     assert!(result.is_ok(), "{:?}", result);
+    assert!(result.unwrap().into_output().as_text().is_some());
 }
 
 #[tokio::test]
@@ -96,8 +385,23 @@ async fn test_optimize() -> io::Result<()> {
 
     let orig_model = tokio::fs::read("models/quantization/test.onnx").await?;
 
-    let optimize_req =
-        OptimizeRequest { source: orig_model, filename: "optimized.onnx".to_string() };
+    let optimize_req = OptimizeRequest::new(orig_model).filename("optimized.onnx");
+
+    let result = client.optimize(optimize_req).await;
+    assert!(result.is_ok(), "{:?}", result);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_optimize_from_path() -> io::Result<()> {
+    env_logger::init();
+
+    let client = FuriosaClient::new("0.2.1").unwrap();
+
+    let optimize_req = OptimizeRequest::from_path("models/quantization/test.onnx");
+    assert_eq!(optimize_req.filename, "test.onnx");
 
     let result = client.optimize(optimize_req).await;
     assert!(result.is_ok(), "{:?}", result);
@@ -114,17 +418,17 @@ async fn test_build_calibration_model() -> io::Result<()> {
 
     let orig_model = tokio::fs::read("models/quantization/test.onnx").await?;
 
-    let optimize_req =
-        OptimizeRequest { source: orig_model, filename: "optimized.onnx".to_string() };
+    let optimize_req = OptimizeRequest::new(orig_model).filename("optimized.onnx");
 
     let result = client.optimize(optimize_req).await;
     assert!(result.is_ok(), "{:?}", result);
     let optimized_model = result.unwrap().to_vec();
 
     let calibration_req = CalibrateRequest {
-        source: optimized_model,
+        source: optimized_model.into(),
         filename: "test.onnx".to_string(),
         input_tensors: vec!["input".to_string()],
+        labels: Default::default(),
     };
 
     let result = client.build_calibration_model(calibration_req).await;
@@ -133,6 +437,30 @@ async fn test_build_calibration_model() -> io::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+#[ignore]
+async fn test_build_calibration_model_from_model() -> io::Result<()> {
+    let client = FuriosaClient::new("0.2.1").unwrap();
+
+    let orig_model = tokio::fs::read("models/quantization/test.onnx").await?;
+
+    let optimize_req = OptimizeRequest::new(orig_model).filename("optimized.onnx");
+
+    let result = client.optimize(optimize_req).await;
+    assert!(result.is_ok(), "{:?}", result);
+    let optimized_model = result.unwrap().to_vec();
+
+    // the input tensors are derived from the graph
+    let calibration_req = CalibrateRequest::from_model(optimized_model)
+        .expect("fail to parse the model")
+        .filename("test.onnx");
+
+    let result = client.build_calibration_model(calibration_req).await;
+    assert!(result.is_ok(), "{:?}", result);
+
+    Ok(())
+}
+
 #[tokio::test]
 #[ignore]
 async fn test_quantize() -> io::Result<()> {
@@ -142,8 +470,7 @@ async fn test_quantize() -> io::Result<()> {
 
     let orig_model = tokio::fs::read("models/quantization/test.onnx").await?;
 
-    let optimize_req =
-        OptimizeRequest { source: orig_model, filename: "optimized.onnx".to_string() };
+    let optimize_req = OptimizeRequest::new(orig_model).filename("optimized.onnx");
 
     let result = client.optimize(optimize_req).await;
     assert!(result.is_ok(), "{:?}", result);
@@ -185,10 +512,11 @@ async fn test_quantize() -> io::Result<()> {
     .expect("fail to parse JSON");
 
     let quantize_req = QuantizeRequest {
-        source: optimized_model,
+        source: optimized_model.into(),
         filename: "test.onnx".to_string(),
         input_tensors: vec!["input".to_string()],
         dynamic_ranges,
+        labels: Default::default(),
     };
 
     let client = FuriosaClient::new("0.2.1").unwrap();
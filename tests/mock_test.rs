@@ -0,0 +1,784 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use furiosa_client::testing::{CompileScript, MockResponse, MockServer};
+use furiosa_client::{
+    BatchMode, BatchOptions, CachePolicy, Cassette, ClientError, CompileCache, CompileOutput,
+    CompileRequest, CompileTaskPhase, CompiledModel, FuriosaClient, Metric, MetricsSink,
+    ModelSource, OptimizeRequest, QuantizeRequest, RateLimit, TargetIr, TaskFilter, VersionInfo,
+};
+use serde_json::json;
+
+fn compile_request(model: &[u8]) -> CompileRequest {
+    CompileRequest::new(json!({"npu": "64dpes"}), model.to_vec())
+}
+
+#[tokio::test]
+async fn test_mock_compile() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+
+    let client = server.client("0.4.0");
+    let enf = client.compile(compile_request(b"model").target_ir(TargetIr::Enf)).await?;
+    assert_eq!(&*enf, b"ENF");
+
+    let tasks = server.tasks();
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].phase, CompileTaskPhase::Succeeded);
+    assert!(tasks[0].finish_time.is_some());
+
+    let requests = server.requests();
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].path, "/api/compiler/v1alpha1/tasks");
+    assert_eq!(requests[0].header("X-FuriosaAI-Access-Key-ID"), Some("mock-key"));
+    assert!(requests[0].body.windows(5).any(|w| w == b"model"));
+    // polls until the task succeeds, downloads the artifact, then fetches the server version
+    assert!(requests.iter().filter(|r| r.path.ends_with(&tasks[0].task_id)).count() >= 2);
+    assert!(requests[requests.len() - 2].path.ends_with("/artifacts/output.enf"));
+    assert_eq!(requests.last().unwrap().path, "/version");
+    // every request has its own ID to be traced
+    let mut request_ids: Vec<_> =
+        requests.iter().filter_map(|r| r.header("X-Request-Id")).collect();
+    request_ids.dedup();
+    assert_eq!(request_ids.len(), requests.len());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_require_compatible_server() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    let version = |version: &str| VersionInfo {
+        version: version.to_string(),
+        revision: String::from("mock"),
+        build_time: String::from("mock"),
+    };
+
+    server.set_version(version("0.4.2"));
+    server.client("0.4.0").require_compatible_server().await?;
+    // a newer server only warns
+    server.set_version(version("0.5.0"));
+    server.client("0.4.0").require_compatible_server().await?;
+    // an older one fails before sending requests
+    server.set_version(version("0.1.0"));
+    let result = server.client("0.4.0").require_compatible_server().await;
+    assert!(matches!(result, Err(ClientError::IncompatibleServer(_))), "{:?}", result.err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_compile_cache() -> Result<(), ClientError> {
+    let dir = std::env::temp_dir().join(format!("furiosa-mock-cache-{}", std::process::id()));
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"ENF1".to_vec()));
+    server.script_compile(CompileScript::succeed(b"ENF2".to_vec()));
+    server.script_compile(CompileScript::succeed(b"ENF3".to_vec()));
+
+    let client = server.client("0.4.0").compile_cache(CompileCache::open(&dir)?);
+    assert_eq!(&*client.compile(compile_request(b"model")).await?, b"ENF1");
+    // hit
+    let cached = client.compile(compile_request(b"model")).await?;
+    assert_eq!(&*cached, b"ENF1");
+    assert!(cached.task_id.is_none());
+    assert_eq!(server.tasks().len(), 1);
+    // another input misses
+    assert_eq!(&*client.compile(compile_request(b"other")).await?, b"ENF2");
+    assert_eq!(server.tasks().len(), 2);
+
+    // refresh compiles again and replaces the cached artifact
+    let refresh = compile_request(b"model").cache_policy(CachePolicy::Refresh);
+    assert_eq!(&*client.compile(refresh).await?, b"ENF3");
+    assert_eq!(server.tasks().len(), 3);
+    assert_eq!(&*client.compile(compile_request(b"model")).await?, b"ENF3");
+    assert_eq!(server.tasks().len(), 3);
+
+    // bypass neither reads nor writes the cache
+    server.script_compile(CompileScript::succeed(b"ENF4".to_vec()));
+    let bypass = compile_request(b"model").cache_policy(CachePolicy::Bypass);
+    assert_eq!(&*client.compile(bypass).await?, b"ENF4");
+    assert_eq!(server.tasks().len(), 4);
+    assert_eq!(&*client.compile(compile_request(b"model")).await?, b"ENF3");
+    assert_eq!(server.tasks().len(), 4);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_input_hash() -> Result<(), ClientError> {
+    let dir = std::env::temp_dir().join(format!("furiosa-input-hash-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join("model.onnx");
+    std::fs::write(&path, b"model")?;
+    let server = MockServer::start();
+    for _ in 0..3 {
+        server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+    }
+
+    // without the cache, the source is hashed while uploading
+    let client = server.client("0.4.0");
+    let spec = json!({"npu": "64dpes"});
+    let file = CompileRequest::new(spec.clone(), ModelSource::from_path(&path));
+    let streamed = client.compile(file).await?;
+    let reader = CompileRequest::new(spec, ModelSource::from_reader(&b"model"[..], 5));
+    let read = client.compile(reader).await?;
+    // a cache lookup hashes the source before uploading
+    let client = server.client("0.4.0").compile_cache(CompileCache::open(dir.join("cache"))?);
+    let cached = client.compile(compile_request(b"model")).await?;
+    assert_eq!(server.tasks().len(), 3);
+    assert_eq!(streamed.input_hash.as_ref().unwrap().len(), 64);
+    assert_eq!(streamed.input_hash, cached.input_hash);
+    assert_eq!(read.input_hash, cached.input_hash);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_missing_ir_artifact() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    let script = CompileScript::succeed(b"ENF".to_vec()).missing_ir_artifact(TargetIr::Gir);
+    server.script_compile(script.clone());
+    server.script_compile(script);
+    let client = server.client("0.4.0");
+
+    // the artifact of an IR other than ENF may not be served by its assumed name
+    let result = client.compile(compile_request(b"model").target_ir(TargetIr::Gir)).await;
+    assert!(matches!(result, Err(ClientError::Unsupported(_))), "{:?}", result.err());
+    let task_id = server.tasks()[0].task_id.clone();
+    let result = client.task_artifact(&task_id, TargetIr::Gir).await;
+    assert!(matches!(result, Err(ClientError::Unsupported(_))), "{:?}", result.err());
+    let output = std::env::temp_dir().join(format!("furiosa-missing-{}.gir", std::process::id()));
+    let result = client.task_artifact_to_path(&task_id, TargetIr::Gir, &output).await;
+    assert!(matches!(result, Err(ClientError::Unsupported(_))), "{:?}", result.err());
+    assert!(!output.exists());
+
+    // while the ENF artifact of an unknown task is still an API error
+    let result = client.task_artifact("unknown", TargetIr::Enf).await;
+    assert!(matches!(result, Err(ClientError::ApiError(_))), "{:?}", result.err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_compiled_model() -> Result<(), ClientError> {
+    let dir = std::env::temp_dir().join(format!("furiosa-compiled-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+    server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+
+    let client = server.client("0.4.0");
+    let model = client.compile(compile_request(b"model").label("team", "vision")).await?;
+    let task = &server.tasks()[0];
+    assert_eq!(model.task_id.as_deref(), Some(task.task_id.as_str()));
+    assert_eq!(model.target_ir.as_str(), "enf");
+    assert_eq!(model.labels.get("team").map(String::as_str), Some("vision"));
+    assert_eq!(model.timings.unwrap().submit_time, task.submit_time);
+    assert_eq!(model.server_version.as_ref().unwrap().version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(model.input_hash.as_ref().unwrap().len(), 64);
+    // the same inputs have the same hash, and the server version is fetched once
+    let again = client.compile(compile_request(b"model").label("team", "vision")).await?;
+    assert_eq!(again.input_hash, model.input_hash);
+    assert_ne!(again.task_id, model.task_id);
+    assert_eq!(server.requests().iter().filter(|r| r.path == "/version").count(), 1);
+
+    let path = dir.join("model.enf");
+    model.save(&path)?;
+    assert!(dir.join("model.enf.manifest.json").exists());
+    let loaded = CompiledModel::load(&path)?;
+    assert_eq!(&*loaded, b"ENF");
+    assert_eq!(loaded.task_id, model.task_id);
+    assert_eq!(loaded.timings, model.timings);
+    assert_eq!(loaded.input_hash, model.input_hash);
+    assert!(!dir.join("model.enf.tmp").exists());
+
+    // nothing is written unless the artifact and the manifest are both written
+    let failed = dir.join("failed.enf");
+    std::fs::create_dir_all(dir.join("failed.enf.manifest.json.tmp"))?;
+    assert!(model.save(&failed).is_err());
+    assert!(!failed.exists());
+    assert!(!dir.join("failed.enf.tmp").exists());
+
+    std::fs::write(&path, b"FNE")?;
+    assert!(matches!(CompiledModel::load(&path), Err(ClientError::ChecksumMismatch(..))));
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_multiple_target_irs() -> Result<(), ClientError> {
+    let dir = std::env::temp_dir().join(format!("furiosa-multi-ir-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let script = CompileScript::succeed(b"lir".to_vec())
+        .ir_artifact(TargetIr::Dfg, b"dfg".to_vec())
+        .ir_artifact(TargetIr::Gir, b"gir".to_vec());
+    let server = MockServer::start();
+    server.script_compile(script.clone());
+    server.script_compile(script);
+
+    let cache = CompileCache::open(dir.join("cache"))?;
+    let client = server.client("0.4.0").compile_cache(cache);
+    let extra_target_irs = vec![TargetIr::Gir, TargetIr::Lir, TargetIr::Dfg];
+    let request = || compile_request(b"model").target_irs(TargetIr::Lir, extra_target_irs.clone());
+    let model = client.compile(request()).await?;
+    assert_eq!(model.target_ir, TargetIr::Lir);
+    assert_eq!(&*model, b"lir");
+    assert_eq!(
+        model.extra_artifacts.keys().copied().collect::<Vec<_>>(),
+        [TargetIr::Dfg, TargetIr::Gir]
+    );
+    assert_eq!(model.output(TargetIr::Gir), Some(CompileOutput::Text(String::from("gir"))));
+    assert!(model.artifact(TargetIr::Enf).is_none());
+
+    // one task for all the IRs
+    assert_eq!(server.tasks().len(), 1);
+    let requests = server.requests();
+    let submit = requests.iter().find(|r| r.method == "POST").unwrap();
+    let parts = String::from_utf8_lossy(&submit.body).matches("name=\"target_ir\"").count();
+    assert_eq!(parts, 3);
+    // the first part is `target_ir`
+    let body = String::from_utf8_lossy(&submit.body);
+    let first = body.split("name=\"target_ir\"").nth(1).unwrap();
+    assert_eq!(first.trim_start().lines().next(), Some("lir"));
+    let downloads = requests.iter().filter(|r| r.path.contains("/artifacts/")).count();
+    assert_eq!(downloads, 3);
+
+    // each artifact is cached as if it was compiled alone
+    let cached = client.compile(request()).await?;
+    assert!(cached.task_id.is_none());
+    assert_eq!(cached.extra_artifacts, model.extra_artifacts);
+    let gir = client.compile(compile_request(b"model").target_ir(TargetIr::Gir)).await?;
+    assert_eq!(&*gir, b"gir");
+    assert_eq!(server.tasks().len(), 1);
+
+    let path = dir.join("model.lir");
+    model.save(&path)?;
+    assert_eq!(std::fs::read(dir.join("model.dfg"))?, b"dfg");
+    let loaded = CompiledModel::load(&path)?;
+    assert_eq!(loaded.extra_artifacts, model.extra_artifacts);
+
+    // a set renamed by the naming of `save` loads as well
+    for name in &["lir", "dfg", "gir", "lir.manifest.json"] {
+        std::fs::rename(dir.join(format!("model.{}", name)), dir.join(format!("foo.{}", name)))?;
+    }
+    let renamed = CompiledModel::load(dir.join("foo.lir"))?;
+    assert_eq!(&*renamed, b"lir");
+    assert_eq!(renamed.extra_artifacts, model.extra_artifacts);
+    for name in &["lir", "dfg", "gir", "lir.manifest.json"] {
+        std::fs::rename(dir.join(format!("foo.{}", name)), dir.join(format!("model.{}", name)))?;
+    }
+    std::fs::write(dir.join("model.gir"), b"rig")?;
+    assert!(matches!(CompiledModel::load(&path), Err(ClientError::ChecksumMismatch(..))));
+
+    let output = dir.join("streamed.lir");
+    let request = request().cache_policy(CachePolicy::Bypass);
+    assert_eq!(client.compile_to_path(request, &output).await?, 9);
+    assert_eq!(std::fs::read(dir.join("streamed.gir"))?, b"gir");
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_compile_failure() {
+    let server = MockServer::start();
+    server.script_compile(CompileScript::fail("error: unsupported operator"));
+    server.script_compile(CompileScript::reject(MockResponse::error(
+        400,
+        "InvalidNpuSpec",
+        "invalid npu spec",
+    )));
+    server.script_compile(
+        CompileScript::succeed(b"ENF".to_vec()).poll_errors(vec![MockResponse::error(
+            503,
+            "Unavailable",
+            "try again",
+        )]),
+    );
+
+    let client = server.client("0.4.0");
+    match client.compile(compile_request(b"model")).await {
+        Err(ClientError::CompilationFailed(logs)) => assert!(logs.contains("unsupported operator")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match client.compile(compile_request(b"model")).await {
+        Err(ClientError::ApiError(msg)) => assert!(msg.contains("invalid npu spec")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    match client.compile(compile_request(b"model")).await {
+        Err(ClientError::ServerError(503, msg)) => assert!(msg.contains("try again")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_mock_credential() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    server.require_credential("mock-key", "mock-secret");
+
+    assert!(server.client("0.4.0").list_tasks(TaskFilter::new()).await?.tasks.is_empty());
+    let client = FuriosaClient::with_credential("0.4.0", server.endpoint(), "mock-key", "wrong")?;
+    assert!(matches!(client.list_tasks(TaskFilter::new()).await, Err(ClientError::ApiError(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_list_tasks() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"ENF1".to_vec()));
+    server.script_compile(CompileScript::fail("unsupported operator"));
+    server.script_compile(CompileScript::succeed(b"ENF2".to_vec()));
+    let client = server.client("0.4.0");
+    for _ in 0..3 {
+        let _ = client.compile(compile_request(b"model")).await;
+    }
+
+    let page = client.list_tasks(TaskFilter::new().page_size(2)).await?;
+    assert_eq!(page.tasks.len(), 2);
+    let token = page.next_page_token.expect("no next page");
+    let page = client.list_tasks(TaskFilter::new().page_size(2).page_token(&token)).await?;
+    assert_eq!(page.tasks.len(), 1);
+    assert!(page.next_page_token.is_none());
+
+    let filter = TaskFilter::new().phase(CompileTaskPhase::Succeeded).page_size(1);
+    let succeeded = client.list_all_tasks(filter).await?;
+    assert_eq!(succeeded.len(), 2);
+    let failed = client.list_tasks(TaskFilter::new().phase(CompileTaskPhase::Failed)).await?;
+    assert_eq!(failed.tasks.len(), 1);
+    let future = client.list_tasks(TaskFilter::new().submitted_after(i64::MAX)).await?;
+    assert!(future.tasks.is_empty());
+
+    let artifact = client.task_artifact(&succeeded[1].task_id, TargetIr::Enf).await?;
+    assert_eq!(artifact.as_bytes(), b"ENF2");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_textual_ir() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"gir dump".to_vec()));
+
+    let client = server.client("0.4.0");
+    let model = client.compile(compile_request(b"model").target_ir(TargetIr::Gir)).await?;
+    let download = server.requests().into_iter().find(|r| r.path.contains("/artifacts/")).unwrap();
+    assert!(download.path.ends_with("/artifacts/output.gir"));
+    assert_eq!(download.header("Accept"), Some("text/plain"));
+    assert_eq!(model.into_output(), CompileOutput::Text(String::from("gir dump")));
+
+    let task_id = &server.tasks()[0].task_id;
+    let output = client.task_artifact(task_id, TargetIr::Gir).await?;
+    assert_eq!(output.as_text(), Some("gir dump"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_labels() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    let client = server.client("0.4.0");
+    client
+        .compile(compile_request(b"model").label("git-sha", "3f2a9c1").label("team", "vision"))
+        .await?;
+    client.compile(compile_request(b"model").label("team", "nlp")).await?;
+
+    let tasks = server.tasks();
+    assert_eq!(tasks[0].labels.get("git-sha").map(String::as_str), Some("3f2a9c1"));
+    assert_eq!(tasks[1].labels.get("team").map(String::as_str), Some("nlp"));
+
+    let page = client.list_tasks(TaskFilter::new().label("team", "vision")).await?;
+    assert_eq!(page.tasks.len(), 1);
+    assert_eq!(page.tasks[0].task_id, tasks[0].task_id);
+
+    let result = client.compile(compile_request(b"model").label("team", "a b")).await;
+    assert!(matches!(result, Err(ClientError::InvalidLabel(_))));
+    assert_eq!(server.tasks().len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_resumed_download() -> Result<(), ClientError> {
+    let artifact: Vec<u8> = (0..64 * 1024).map(|i| (i % 251) as u8).collect();
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(artifact.clone()).interrupt_download_at(1000));
+
+    let dir = std::env::temp_dir().join(format!("furiosa-mock-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let output = dir.join("output.enf");
+    let size = server.client("0.4.0").compile_to_path(compile_request(b"model"), &output).await?;
+    assert_eq!(size, artifact.len() as u64);
+    assert_eq!(std::fs::read(&output)?, artifact);
+    std::fs::remove_dir_all(&dir)?;
+
+    let downloads: Vec<_> =
+        server.requests().into_iter().filter(|r| r.path.contains("/artifacts/")).collect();
+    assert_eq!(downloads.len(), 2);
+    assert!(downloads[1].header("range").is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_download_after_complete_part() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    let script = CompileScript::succeed(b"ENF".to_vec()).interrupt_download_at(3);
+    server.script_compile(script);
+    let dir = std::env::temp_dir().join(format!("furiosa-complete-part-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let client = server.client("0.4.0");
+
+    // the range after the whole artifact isn't satisfiable, which completes the download
+    let output = dir.join("output.enf");
+    assert_eq!(client.compile_to_path(compile_request(b"model"), &output).await?, 3);
+    assert_eq!(std::fs::read(&output)?, b"ENF");
+    let downloads: Vec<_> =
+        server.requests().into_iter().filter(|r| r.path.contains("/artifacts/")).collect();
+    assert_eq!(downloads[1].header("range"), Some("bytes=3-"));
+
+    // a failed download doesn't leave the temporary file
+    let result = client.task_artifact_to_path("unknown", TargetIr::Enf, dir.join("x.enf")).await;
+    assert!(result.is_err());
+    let mut files: Vec<_> = std::fs::read_dir(&dir)?.map(|e| e.unwrap().file_name()).collect();
+    files.sort();
+    assert_eq!(files, vec!["output.enf"]);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_download_keeps_part_after_errors() -> Result<(), ClientError> {
+    let artifact: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+    let unavailable = MockResponse::error(503, "Unavailable", "unavailable");
+    let script = CompileScript::succeed(artifact.clone())
+        .interrupt_download_at(1000)
+        .resume_errors(vec![unavailable; 4]);
+    let server = MockServer::start();
+    server.script_compile(script);
+    let dir = std::env::temp_dir().join(format!("furiosa-keep-part-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let client = server.client("0.4.0");
+
+    // the resumes failing with 5xx keep the received bytes
+    let output = dir.join("output.enf");
+    let result = client.compile_to_path(compile_request(b"model"), &output).await;
+    assert!(matches!(result, Err(ClientError::Download(_))), "{:?}", result);
+    let files: Vec<_> = std::fs::read_dir(&dir)?.map(|e| e.unwrap().path()).collect();
+    assert_eq!(files.len(), 1);
+    assert_eq!(std::fs::metadata(&files[0])?.len(), 1000);
+
+    // and the next download of the artifact resumes from them
+    let task_id = server.tasks()[0].task_id.clone();
+    assert_eq!(client.task_artifact_to_path(&task_id, TargetIr::Enf, &output).await?, 4096);
+    assert_eq!(std::fs::read(&output)?, artifact);
+    let requests = server.requests();
+    assert_eq!(requests.last().unwrap().header("range"), Some("bytes=1000-"));
+
+    // the temporary files of other URLs are removed
+    let stale = dir.join("output.enf.0123456789ab.part");
+    std::fs::write(&stale, b"ENF")?;
+    client.task_artifact_to_path(&task_id, TargetIr::Enf, &output).await?;
+    assert!(!stale.exists());
+    let files: Vec<_> = std::fs::read_dir(&dir)?.map(|e| e.unwrap().file_name()).collect();
+    assert_eq!(files, vec!["output.enf"]);
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_streaming_upload() -> Result<(), ClientError> {
+    let model = vec![7u8; 256 * 1024];
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"ENF".to_vec()).delay(Duration::from_millis(5)));
+
+    let source = ModelSource::from_reader(std::io::Cursor::new(model.clone()), model.len() as u64);
+    let request = CompileRequest::new(json!({}), source);
+    assert_eq!(&*server.client("0.4.0").compile(request).await?, b"ENF");
+    assert!(server.requests()[0].body.len() > model.len());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mock_dss() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    server.respond_dss("optimize", MockResponse::bytes(b"optimized".to_vec()));
+    server.respond_dss("quantize", MockResponse::error(500, "Internal", "out of memory"));
+
+    let client = server.client("0.4.0");
+    let optimized = client.optimize(OptimizeRequest::new(b"onnx".to_vec())).await?;
+    assert_eq!(&*optimized, b"optimized");
+
+    let request = QuantizeRequest::from_path("models/quantization/test.onnx", Default::default())?;
+    match client.quantize(request).await {
+        Err(ClientError::ServerError(500, msg)) => assert!(msg.contains("out of memory")),
+        other => panic!("unexpected result: {:?}", other),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cassette_replay() -> Result<(), ClientError> {
+    let dir = std::env::temp_dir().join(format!("furiosa-cassette-{}", std::process::id()));
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+    server.respond_dss("optimize", MockResponse::bytes(b"optimized".to_vec()));
+
+    let client = server.client("0.4.0").cassette(Cassette::record(&dir)?);
+    assert_eq!(&*client.compile(compile_request(b"model")).await?, b"ENF");
+    assert_eq!(&*client.optimize(OptimizeRequest::new(b"onnx".to_vec())).await?, b"optimized");
+    let endpoint = server.endpoint();
+    drop(server);
+
+    let cassette = std::fs::read_to_string(dir.join("cassette.json"))?;
+    assert!(!cassette.contains("mock-secret"));
+
+    let client = FuriosaClient::with_credential("0.4.0", endpoint, "", "")?
+        .poll_interval(Duration::from_millis(1))
+        .cassette(Cassette::replay(&dir)?);
+    assert_eq!(&*client.compile(compile_request(b"model")).await?, b"ENF");
+    assert_eq!(&*client.optimize(OptimizeRequest::new(b"onnx".to_vec())).await?, b"optimized");
+    assert!(matches!(client.list_tasks(TaskFilter::new()).await, Err(ClientError::Cassette(_))));
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+/// An example exporter which renders metrics in Prometheus text format
+#[derive(Default)]
+struct PrometheusSink {
+    /// Values of counters and sums of histograms by names with labels
+    values: Mutex<BTreeMap<String, f64>>,
+}
+
+impl PrometheusSink {
+    fn add(&self, name: &str, labels: &str, value: f64) {
+        *self.values.lock().unwrap().entry(format!("{}{{{}}}", name, labels)).or_default() += value;
+    }
+
+    fn observe(&self, name: &str, labels: &str, value: Duration) {
+        self.add(&format!("{}_sum", name), labels, value.as_secs_f64());
+        self.add(&format!("{}_count", name), labels, 1.0);
+    }
+
+    fn render(&self) -> String {
+        let mut output = String::new();
+        for (name, value) in self.values.lock().unwrap().iter() {
+            writeln!(output, "{} {}", name, value).unwrap();
+        }
+        output
+    }
+}
+
+impl MetricsSink for PrometheusSink {
+    fn record(&self, metric: &Metric) {
+        match metric {
+            Metric::Latency { operation, elapsed, success } => self.observe(
+                "furiosa_operation_seconds",
+                &format!("operation=\"{}\",success=\"{}\"", operation, success),
+                *elapsed,
+            ),
+            Metric::QueueTime(time) => self.observe("furiosa_compile_queue_seconds", "", *time),
+            Metric::RunTime(time) => self.observe("furiosa_compile_run_seconds", "", *time),
+            Metric::BytesUploaded { operation, bytes } => self.add(
+                "furiosa_uploaded_bytes_total",
+                &format!("operation=\"{}\"", operation),
+                *bytes as f64,
+            ),
+            Metric::BytesDownloaded { operation, bytes } => self.add(
+                "furiosa_downloaded_bytes_total",
+                &format!("operation=\"{}\"", operation),
+                *bytes as f64,
+            ),
+            Metric::Error { operation, code } => self.add(
+                "furiosa_errors_total",
+                &format!("operation=\"{}\",code=\"{}\"", operation, code),
+                1.0,
+            ),
+            Metric::Retry { operation } => {
+                self.add("furiosa_retries_total", &format!("operation=\"{}\"", operation), 1.0)
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_prometheus_metrics() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(vec![0u8; 2048]).interrupt_download_at(1024));
+    server.script_compile(CompileScript::fail("error"));
+
+    let sink = Arc::new(PrometheusSink::default());
+    let client = server.client("0.4.0").metrics_sink(sink.clone());
+    let output = std::env::temp_dir().join(format!("furiosa-metrics-{}.enf", std::process::id()));
+    client.compile_to_path(compile_request(&[1u8; 100]), &output).await?;
+    let task_id = server.tasks()[0].task_id.clone();
+    client.task_artifact_to_path(&task_id, TargetIr::Enf, &output).await?;
+    std::fs::remove_file(&output)?;
+    assert!(client.compile(compile_request(b"model")).await.is_err());
+
+    let metrics = sink.render();
+    assert!(metrics
+        .contains("furiosa_operation_seconds_count{operation=\"compile\",success=\"true\"} 1"));
+    assert!(metrics.contains("furiosa_compile_queue_seconds_count{} 2"));
+    assert!(metrics.contains("furiosa_uploaded_bytes_total{operation=\"compile\"} 105"));
+    assert!(metrics.contains("furiosa_downloaded_bytes_total{operation=\"compile\"} 2048"));
+    assert!(metrics.contains("furiosa_downloaded_bytes_total{operation=\"task_artifact\"} 2048"));
+    assert!(metrics.contains("furiosa_retries_total{operation=\"download\"} 1"));
+    assert!(metrics
+        .contains("furiosa_errors_total{operation=\"compile\",code=\"compilation_failed\"} 1"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_compile_many() {
+    use futures_util::StreamExt;
+
+    let server = MockServer::start();
+    let client = server.client("0.4.0");
+    let requests = || (0..3).map(|i| compile_request(&[i]));
+
+    server.script_compile(CompileScript::succeed(b"A".to_vec()));
+    server.script_compile(CompileScript::fail("error"));
+    server.script_compile(CompileScript::succeed(b"B".to_vec()));
+    let mut results: Vec<_> = client.compile_many(requests(), BatchOptions::new(2)).collect().await;
+    results.sort_by_key(|(index, _)| *index);
+    assert_eq!(results.iter().map(|(index, _)| *index).collect::<Vec<_>>(), vec![0, 1, 2]);
+    assert_eq!(results.iter().filter(|(_, result)| result.is_ok()).count(), 2);
+
+    server.script_compile(CompileScript::reject(MockResponse::error(503, "Unavailable", "busy")));
+    let options = BatchOptions::new(1).retries(1).retry_delay(Duration::from_millis(1));
+    let results: Vec<_> = client.compile_many(requests(), options).collect().await;
+    assert!(results.iter().all(|(_, result)| result.is_ok()));
+    assert_eq!(server.tasks().len(), 6);
+
+    server.script_compile(CompileScript::fail("error"));
+    let options = BatchOptions::new(1).mode(BatchMode::FailFast);
+    let results: Vec<_> = client.compile_many(requests(), options).collect().await;
+    assert_eq!(results.len(), 1);
+    assert!(matches!(results[0], (0, Err(ClientError::CompilationFailed(_)))));
+
+    // a 4xx response fails in the same way again, so it isn't retried
+    server.script_compile(CompileScript::reject(MockResponse::error(400, "Invalid", "invalid")));
+    let options = BatchOptions::new(1).retries(1).retry_delay(Duration::from_millis(1));
+    let results: Vec<_> = client.compile_many(requests().take(1), options).collect().await;
+    assert!(matches!(results[0], (0, Err(ClientError::ApiError(_)))));
+}
+
+#[tokio::test]
+async fn test_compile_many_cancels_on_failure() {
+    use futures_util::StreamExt;
+
+    let server = MockServer::start();
+    let client = server.client("0.4.0");
+    // whichever is submitted first fails, while the other one is still pending
+    let pending = vec![CompileTaskPhase::Pending; 1000];
+    server.script_compile(CompileScript::fail("error"));
+    server.script_compile(CompileScript::succeed(b"A".to_vec()).phases(pending));
+
+    let requests = (0..2).map(|i| compile_request(&[i]));
+    let options = BatchOptions::new(2).mode(BatchMode::FailFast);
+    let results: Vec<_> = client.compile_many(requests, options).collect().await;
+    assert_eq!(results.len(), 1);
+    assert!(matches!(results[0].1, Err(ClientError::CompilationFailed(_))));
+
+    let tasks = server.tasks();
+    assert_eq!(tasks.len(), 2);
+    assert!(tasks.iter().all(|task| task.phase.is_completed()));
+    assert!(tasks.iter().any(|task| task.error_message.as_deref() == Some("cancelled")));
+    assert_eq!(server.requests().iter().filter(|r| r.method == "DELETE").count(), 1);
+}
+
+#[tokio::test]
+async fn test_rate_limit() -> Result<(), ClientError> {
+    let server = MockServer::start();
+    server.set_quota(100, 42, 7);
+    server.script_compile(
+        CompileScript::succeed(b"ENF".to_vec())
+            .poll_errors(vec![MockResponse::RateLimited { retry_after: 0 }]),
+    );
+    server.script_compile(CompileScript::reject(MockResponse::RateLimited { retry_after: 0 }));
+    server.script_compile(CompileScript::succeed(b"RETRIED".to_vec()));
+    server.script_compile(CompileScript::reject(MockResponse::RateLimited { retry_after: 3 }));
+    server.script_compile(CompileScript::reject(MockResponse::RateLimited { retry_after: 3600 }));
+
+    let client = server.client("0.4.0").submit_rate_limit(RateLimit::per_second(20.0)?);
+    assert!(client.quota().is_none());
+    // polls are sent again after Retry-After
+    let polled = client.compile(compile_request(b"model")).await?;
+    assert_eq!(&*polled, b"ENF");
+    // uploads are built again from the source, which is hashed again
+    let retried = client.compile(compile_request(b"model")).await?;
+    assert_eq!(&*retried, b"RETRIED");
+    assert_eq!(retried.input_hash, polled.input_hash);
+    // readers cannot be read again
+    let reader = ModelSource::from_reader(&b"model"[..], 5);
+    match client.compile(CompileRequest::new(json!({}), reader)).await {
+        Err(ClientError::RateLimited(_, retry_after)) => {
+            assert_eq!(retry_after, Some(Duration::from_secs(3)))
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    // too long Retry-After isn't waited for
+    match client.compile(compile_request(b"model")).await {
+        Err(ClientError::RateLimited(_, retry_after)) => {
+            assert_eq!(retry_after, Some(Duration::from_secs(3600)))
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let quota = client.quota().unwrap();
+    assert_eq!((quota.rate_limit, quota.rate_remaining), (Some(100), Some(42)));
+    assert_eq!((quota.quota_limit, quota.quota_remaining), (None, Some(7)));
+
+    // the second submission waits for a token
+    let start = std::time::Instant::now();
+    for _ in 0..2 {
+        client.compile(compile_request(b"model")).await?;
+    }
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    Ok(())
+}
+
+#[cfg(feature = "blocking")]
+#[test]
+fn test_blocking_client() -> Result<(), ClientError> {
+    use furiosa_client::blocking;
+
+    let server = MockServer::start();
+    server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+    server.script_compile(CompileScript::succeed(b"ENF".to_vec()));
+    server.respond_dss("optimize", MockResponse::bytes(b"OPTIMIZED".to_vec()));
+
+    let client = blocking::FuriosaClient::from_async(server.client("0.4.0"))?;
+    assert_eq!(client.endpoint(), server.endpoint());
+    assert_eq!(&*client.compile(compile_request(b"model"))?, b"ENF");
+    let mut enf = Vec::new();
+    assert_eq!(client.compile_to_writer(compile_request(b"model"), &mut enf)?, 3);
+    assert_eq!(enf, b"ENF");
+    assert_eq!(&*client.optimize(OptimizeRequest::new(b"model".to_vec()))?, b"OPTIMIZED");
+
+    let tasks = client.list_all_tasks(TaskFilter::new())?;
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(client.get_task(&tasks[0].task_id)?.phase, CompileTaskPhase::Succeeded);
+    assert_eq!(client.task_artifact(&tasks[1].task_id, TargetIr::Enf)?.as_bytes(), b"ENF");
+
+    // shares a runtime owned by the application
+    let runtime = tokio::runtime::Runtime::new()?;
+    let client =
+        blocking::FuriosaClient::with_runtime(server.client("0.4.0"), runtime.handle().clone())?;
+    assert_eq!(client.list_tasks(TaskFilter::new())?.tasks.len(), 2);
+
+    // nothing drives a current-thread runtime while the calling thread blocks
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    let result =
+        blocking::FuriosaClient::with_runtime(server.client("0.4.0"), runtime.handle().clone());
+    assert!(matches!(result, Err(ClientError::Io(_))));
+    Ok(())
+}
+
+#[cfg(feature = "blocking")]
+#[tokio::test]
+async fn test_blocking_client_in_runtime() -> Result<(), ClientError> {
+    use furiosa_client::blocking;
+
+    let server = MockServer::start();
+    let client = blocking::FuriosaClient::from_async(server.client("0.4.0"))?;
+    let result = client.list_tasks(TaskFilter::new());
+    assert!(matches!(result, Err(ClientError::NestedRuntime)));
+    Ok(())
+}